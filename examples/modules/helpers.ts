@@ -0,0 +1,29 @@
+// adds two numbers
+export function add(a: number, b: number): number {
+    return a + b;
+}
+
+// applies `f` twice
+export function twice(f: (x: number) => number, x: number): number {
+    return f(f(x));
+}
+
+export const inc = (x: number): number => x + 1;
+
+// the minimum of two numbers
+export const min = (a: number, b: number): number => a <= b ? a : b;
+
+// two types with the same fields, told apart by the expected type
+export interface Point {
+    x: number;
+    y: number;
+}
+
+export interface Offset {
+    x: number;
+    y: number;
+}
+
+export function translate(p: Point, d: Offset): Point {
+    return { x: p.x + d.x, y: p.y + d.y };
+}
@@ -0,0 +1,20 @@
+import { add, translate, Offset, Point } from "./helpers";
+
+// computes `n + (n - 1) + ... + 1`, recursively
+function sum_to(n: number): number {
+    if (n <= 0)
+        return 0;
+    else
+        return add(n, sum_to(n - 1));
+}
+
+// moves the origin by `(n, n)`
+function diagonal(n: number): Point {
+    const d: Offset = { x: n, y: n };
+    return translate({ x: 0, y: 0 }, d);
+}
+
+// not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+export function spend(sum: number, a: number, b: number): boolean {
+    return sum == add(a, b);
+}
@@ -0,0 +1,30 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    type ByteArray = Uint8Array;
+
+    interface Datum {
+        owner: ByteArray;
+        tag: Uint8Array;
+    }
+
+    function prefix(bytes: ByteArray): ByteArray {
+        return bytes.slice(0, 4);
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: Datum, redeemer: ByteArray, ctx: number): boolean {
+        const tagged = datum.owner.concat(datum.tag);
+        return prefix(redeemer) == hex`deadbeef`
+            && redeemer.slice(4) == tagged
+            && datum.tag.length <= 8n
+            && datum.owner < redeemer;
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
@@ -0,0 +1,40 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    interface Bid {
+        bidder: string;
+        amount: bigint;
+    }
+
+    interface Datum {
+        owner: string;
+        bid: Bid;
+    }
+
+    function best({ amount: a }: Bid, [first, ...rest]: bigint[]): bigint {
+        let best = a;
+        for (const amount of rest) {
+            best = amount > best ? amount : best;
+        }
+        return first > best ? first : best;
+    }
+
+    // the destructured parameter gets a name other than `param_0`, which is taken
+    function plus_first([x]: bigint[], param_0: bigint): bigint {
+        return x + param_0;
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: Datum, redeemer: [bigint, bigint[]], ctx: number): boolean {
+        const { owner, bid: { bidder, amount } } = datum;
+        const [minimum, offers] = redeemer;
+        return bidder == owner || best(datum.bid, offers) >= minimum + amount;
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
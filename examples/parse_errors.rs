@@ -0,0 +1,36 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    interface Datum {
+        owner: string;
+        deadline: bigint;
+        amounts: bigint[];
+    }
+
+    function total(amounts: bigint[]): bigint {
+        let sum = 0n;
+        for (const amount of amounts) {
+            if (amount < 0n) {
+                throw new Error("negative amount");
+            }
+            sum += amount;
+        }
+        return sum;
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: Datum, redeemer: bigint, ctx: number): boolean {
+        assert(redeemer > datum.deadline, "too early");
+        if (datum.owner == "") {
+            throw new Error("no owner");
+        }
+        return total(datum.amounts) > 0n;
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
@@ -0,0 +1,23 @@
+use indoc::indoc;
+use jutus::program::{CompilerOptions, Error, NumericSemantics};
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    // With a scale of 2, `1.05` is `105` on-chain and `*` and `/` rescale their result
+    function with_interest(amount: number, rate: number): number {
+        return amount * (1 + rate / 100);
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: number, redeemer: number, ctx: number): boolean {
+        return with_interest(datum, 1.05) <= redeemer % 0.25;
+    }
+  "#};
+
+    let options = CompilerOptions {
+        numbers: NumericSemantics::FixedPoint { scale: 2 },
+    };
+    js_compiler::parser_main_helper_with_options(code, Path::new(file!()), options)
+}
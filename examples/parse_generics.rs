@@ -0,0 +1,67 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    function orElse<T>(value: T | undefined, otherwise: T): T {
+        return value ?? otherwise;
+    }
+
+    function count<T>(xs: T[]): bigint {
+        let n = 0n;
+        for (const x of xs) {
+            n += 1n;
+        }
+        return n;
+    }
+
+    function contains<T>(xs: T[], item: T): boolean {
+        let found = false;
+        for (const x of xs) {
+            if (x === item) {
+                found = true;
+            }
+        }
+        return found;
+    }
+
+    const pair = <A, B>(a: A, b: B): [A, B] => [a, b];
+
+    interface Box<T> {
+        value: T;
+    }
+
+    type Outcome<T, E> = { kind: "ok"; value: T } | { kind: "failed"; error: E };
+
+    function unbox<T>(box: Box<T>): T {
+        return box.value;
+    }
+
+    function outcomeOr<T, E>(outcome: Outcome<T, E>, otherwise: T): T {
+        switch (outcome.kind) {
+            case "ok":
+                return outcome.value;
+            case "failed":
+                return otherwise;
+        }
+    }
+
+    // `K` and `k` are distinct type variables, even though aiken's are lowercase
+    function swap<K, k>(entry: [K, k]): [k, K] {
+        return [entry[1], entry[0]];
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: bigint[], redeemer: bigint | undefined, ctx: number): boolean {
+        const limit = orElse(redeemer, 10n);
+        const checked = pair(limit, contains(datum, limit));
+        const boxed: Box<bigint> = { value: limit };
+        const outcome: Outcome<bigint, string> = { kind: "ok", value: unbox(boxed) };
+        return count(datum) < outcomeOr(outcome, limit) && checked[1] && swap(checked)[0];
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
@@ -0,0 +1,84 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+// A regression corpus for `if`/`else` chains
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    // the maximum of two numbers
+    function max(a, b) {
+        if(a >= b)
+            return a;
+        else
+            return b;
+    }
+
+    // the maximum of two numbers, with blocks
+    function max_block(a: number, b: number): number {
+        if (a >= b) {
+            return a;
+        } else {
+            return b;
+        }
+    }
+
+    // the maximum of three numbers, with nested ifs
+    function max3(a: number, b: number, c: number): number {
+        if (a >= b) {
+            if (a >= c) return a;
+            else return c;
+        } else {
+            if (b >= c) return b;
+            else return c;
+        }
+    }
+
+    // the sign of a number, with an `else if` chain
+    function sign(n: number): number {
+        if (n > 0)
+            return 1;
+        else if (n < 0)
+            return -1;
+        else
+            return 0;
+    }
+
+    // `n` clamped to `[lo, hi]`, with an `else { if ... }` chain
+    function clamp(n: number, lo: number, hi: number): number {
+        if (n < lo) {
+            return lo;
+        } else {
+            if (n > hi) {
+                return hi;
+            } else {
+                return n;
+            }
+        }
+    }
+
+    // early return, as a guard clause
+    function check(ok: boolean, a: number): boolean {
+        if (!ok) return false;
+        let b = a + 1;
+        return b > 0;
+    }
+
+    // early return from an `if` without an `else`, nested in another one
+    function pick(a: number, b: number): number {
+        if (a > 0) {
+            if (b > 0) return a + b;
+            let c = a - b;
+            return c;
+        }
+        return b;
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(n: number, a: number, b: number): boolean {
+        return max(a, b) == max_block(a, b) && max3(n, a, b) >= clamp(n, a, b) && sign(0) == 0 && check(true, pick(a, b));
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
@@ -0,0 +1,43 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    function sum(xs: bigint[]): bigint {
+        let total = 0n;
+        for (const x of xs) {
+            total += x;
+        }
+        return total;
+    }
+
+    function count_above(xs: Array<bigint>, min: bigint): bigint {
+        let count = 0n;
+        for (const x of xs) {
+            count = x > min ? count + 1n : count;
+        }
+        return count;
+    }
+
+    function bounds(xs: bigint[], start: bigint): [bigint, bigint] {
+        let lo = start;
+        let hi = start;
+        for (const x of xs) {
+            lo = x < lo ? x : lo;
+            hi = x > hi ? x : hi;
+        }
+        return [lo, hi];
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: bigint[], redeemer: [bigint, bigint], ctx: number): boolean {
+        const limits = [1n, 2n, 3n];
+        const range = bounds(datum, redeemer[0]);
+        return sum(datum) > redeemer[1] && count_above(limits, range[0]) == 2n;
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
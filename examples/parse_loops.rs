@@ -0,0 +1,41 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    function sum_to(n: bigint): bigint {
+        let total = 0n;
+        for (let i = 1n; i <= n; i++) {
+            total += i;
+        }
+        return total;
+    }
+
+    function log2(n: bigint): bigint {
+        let steps = 0n;
+        let rest = n;
+        while (rest > 1n) {
+            rest = rest / 2n;
+            steps = steps + 1n;
+        }
+        return steps;
+    }
+
+    function count_multiples(xs: bigint[], k: bigint): bigint {
+        let count = 0n;
+        for (const x of xs) {
+            count += x % k == 0n ? 1n : 0n;
+        }
+        return count;
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: bigint, redeemer: bigint[], ctx: number): boolean {
+        return sum_to(datum) == 55n && log2(datum) == 3n && count_multiples(redeemer, 2n) > 0n;
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
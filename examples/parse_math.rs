@@ -0,0 +1,24 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    // `**` and `Math.pow` become calls to a square-and-multiply helper
+    function compound(principal: bigint, rate: bigint, periods: bigint): bigint {
+        return principal * (100n + rate) ** periods / 100n ** periods;
+    }
+
+    function spread(bid: bigint, ask: bigint, floor: bigint): bigint {
+        return Math.max(Math.abs(ask - bid), floor, Math.pow(2n, 10n));
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: bigint, redeemer: bigint, ctx: number): boolean {
+        return compound(datum, 5n, 3n) >= Math.min(redeemer, spread(datum, redeemer, 1n));
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
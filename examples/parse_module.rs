@@ -0,0 +1,11 @@
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    // The entry point imports from `examples/modules/helpers.ts`
+    let fname = Path::new("examples/modules/main.ts");
+    let code = std::fs::read_to_string(fname).map_err(|_| Error::BadFilename(fname.into()))?;
+
+    js_compiler::parser_main_helper(&code, fname)
+}
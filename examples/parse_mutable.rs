@@ -0,0 +1,51 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    function fee(amount: bigint, big: boolean): bigint {
+        let fee = 1n;
+        if (big) {
+            fee = 2n;
+        }
+        fee = fee * amount;
+        return fee;
+    }
+
+    function split(xs: bigint[], pivot: bigint): [bigint, bigint] {
+        let below = 0n;
+        let above = 0n;
+        for (const x of xs) {
+            if (x < pivot) {
+                below += x;
+            } else {
+                above += x;
+            }
+        }
+        return [below, above];
+    }
+
+    function collatz_steps(n: bigint): bigint {
+        let steps = 0n;
+        while (n != 1n) {
+            if (n % 2n == 0n) {
+                n = n / 2n;
+            } else {
+                n = 3n * n + 1n;
+            }
+            steps++;
+        }
+        return steps;
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: bigint, redeemer: bigint[], ctx: number): boolean {
+        const parts = split(redeemer, datum);
+        return fee(parts[0], parts[1] > datum) < collatz_steps(datum);
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
@@ -0,0 +1,24 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    // Lovelace amounts can exceed what a `number` represents exactly
+    function supply(): bigint {
+        return 45000000000000000n;
+    }
+
+    function share(amount: bigint, parts: bigint): bigint {
+        return amount / parts;
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: bigint, redeemer: bigint, ctx: number): boolean {
+        return share(supply(), 1000000000000000000000n) < datum + redeemer * 2n;
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
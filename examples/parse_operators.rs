@@ -0,0 +1,28 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    interface Asset {
+        policy: string;
+        name: string;
+    }
+
+    function label(asset: Asset): string {
+        return asset.policy + "." + asset.name;
+    }
+
+    function in_range(amount: bigint, low: bigint, high: bigint): boolean {
+        return low < amount && amount <= high;
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: Asset, redeemer: Asset, ctx: number): boolean {
+        return datum == redeemer || label(datum) == "ada." + redeemer.name;
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
@@ -0,0 +1,43 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    interface Beneficiary {
+        name: string;
+        share?: bigint;
+    }
+
+    interface Datum {
+        owner: string;
+        beneficiary?: Beneficiary;
+        deadline: bigint | undefined;
+    }
+
+    function share(datum: Datum): bigint {
+        return datum.beneficiary?.share ?? 0n;
+    }
+
+    function extend(datum: Datum, by: bigint): Datum {
+        const deadline = datum.deadline ?? 0n;
+        return { owner: datum.owner, deadline: deadline + by };
+    }
+
+    function name(beneficiary: Beneficiary | undefined): string | undefined {
+        return beneficiary?.name;
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: Datum, redeemer: bigint, ctx: number): boolean {
+        const extended = extend(datum, redeemer);
+        const beneficiary: Beneficiary = { name: datum.owner };
+        return datum.deadline !== undefined
+            && share(extended) == 0n
+            && name(beneficiary) === name(datum.beneficiary);
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
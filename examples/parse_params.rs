@@ -0,0 +1,36 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    function min_fee(): bigint {
+        return 2n;
+    }
+
+    // defaults are computed by `fee`, so `min_fee` is the function above at every call
+    function fee(amount: bigint, rate: bigint = 3n, floor: bigint = min_fee()): bigint {
+        const fee = amount * rate / 100n;
+        return fee < floor ? floor : fee;
+    }
+
+    function total(base: bigint, ...amounts: bigint[]): bigint {
+        let total = base, count = 0n;
+        for (const amount of amounts) {
+            total += amount;
+            count++;
+        }
+        return count > 0n ? total : base;
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: bigint, redeemer: bigint[], ctx: number): boolean {
+        const min_fee = 1n;
+        const a = fee(datum), b = fee(datum, 5n + min_fee);
+        return total(0n, a, b) <= total(datum, ...redeemer);
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
@@ -0,0 +1,24 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    interface Datum {
+        owner: string;
+        deadline: bigint;
+    }
+
+    function describe(datum: Datum, expired: boolean): string {
+        return `owner: ${datum.owner}, deadline: ${datum.deadline}, expired: ${expired}`;
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: Datum, redeemer: bigint, ctx: number): boolean {
+        return describe(datum, redeemer > datum.deadline) != `owner: ${datum.owner}`;
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
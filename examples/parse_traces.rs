@@ -0,0 +1,36 @@
+use indoc::indoc;
+use jutus::program::{CompilerOptions, Error, Traces};
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    interface Datum {
+        owner: string;
+        deadline: bigint;
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: Datum, redeemer: bigint, ctx: number): boolean {
+        console.log("spending", datum.owner, "at", redeemer);
+        let late = false;
+        if (redeemer > datum.deadline) {
+            console.log("late by", redeemer - datum.deadline);
+            late = true;
+        }
+        if (late) {
+            throw new Error("too late");
+        }
+        return true;
+    }
+  "#};
+
+    // With traces, then without, as for production builds
+    js_compiler::parser_main_helper(code, Path::new(file!()))?;
+
+    let options = CompilerOptions {
+        traces: Traces::Strip,
+        ..Default::default()
+    };
+    js_compiler::parser_main_helper_with_options(code, Path::new(file!()), options)
+}
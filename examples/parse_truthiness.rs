@@ -0,0 +1,31 @@
+use indoc::indoc;
+use jutus::program::{CompilerOptions, Error, Truthiness};
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    // With `Truthiness::Coerce`, `count` means `count != 0`, `owner` means `owner != ""`
+    // and `!signers` means `signers == []`
+    function claimable(count: bigint, owner: string, signers: string[]): boolean {
+        return count && owner ? !signers : false;
+    }
+
+    // `deadline` is truthy when it is defined and not `0`, `memo` when it is defined and
+    // not empty
+    function has_deadline(deadline: bigint | undefined, memo: string | undefined): boolean {
+        return deadline ? true : memo ? true : false;
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: string, redeemer: string[], ctx: number): boolean {
+        return datum !== "" && claimable(1n, datum, redeemer) && has_deadline(1n, undefined);
+    }
+  "#};
+
+    let options = CompilerOptions {
+        truthiness: Truthiness::Coerce,
+        ..Default::default()
+    };
+    js_compiler::parser_main_helper_with_options(code, Path::new(file!()), options)
+}
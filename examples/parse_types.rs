@@ -0,0 +1,63 @@
+use indoc::indoc;
+use jutus::program::Error;
+use jutus::*;
+use std::path::Path;
+
+fn main() -> Result<(), Error> {
+    let code = indoc! {r#"
+    interface Datum {
+        owner: string;
+        deadline: bigint;
+    }
+
+    type Redeemer = { signer: string; now: bigint };
+
+    type Action =
+        | { kind: "claim" }
+        | { kind: "cancel"; by: string }
+        | { kind: "extend"; by: string; until: bigint };
+
+    function make_datum(owner: string, deadline: bigint): Datum {
+        return { owner, deadline: deadline };
+    }
+
+    function allowed(datum: Datum, action: Action, signer: string): boolean {
+        switch (action.kind) {
+            case "claim":
+                return signer == datum.owner;
+            case "cancel":
+                return action.by == signer;
+            case "extend":
+                return action.by == datum.owner && action.until > datum.deadline;
+        }
+    }
+
+    function is_final(action: Action): boolean {
+        switch (action.kind) {
+            case "claim":
+            case "cancel":
+                return true;
+            default:
+                return false;
+        }
+    }
+
+    // `by` is shared by both members, so it can be read in their common case
+    function extended_by(action: Action): string {
+        switch (action.kind) {
+            case "cancel":
+            case "extend":
+                return action.by;
+            default:
+                return "";
+        }
+    }
+
+    // not a real validator (one of: "spend", "cert", "mint", "withdrawal").
+    function spend(datum: Datum, redeemer: Redeemer, ctx: number): boolean {
+        return datum.owner == redeemer.signer && redeemer.now >= datum.deadline;
+    }
+  "#};
+
+    js_compiler::parser_main_helper(code, Path::new(file!()))
+}
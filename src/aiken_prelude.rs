@@ -0,0 +1,290 @@
+//! Functions that the generated aiken code relies on, for what has no direct equivalent in
+//! aiken, such as `**`. They are added to a module only when it uses them.
+
+use aiken_lang::ast::{
+    Annotation, Arg, ArgName, AssignmentKind, BinOp, CallArg, Function, IfBranch,
+    UntypedDefinition, UntypedPattern,
+};
+use aiken_lang::expr::UntypedExpr;
+use vec1::vec1;
+
+use crate::ir_to_unode::no_span;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PreludeFn {
+    /// `base ** exponent`, by square-and-multiply
+    Pow,
+    /// `Math.abs(x)`
+    Abs,
+    /// `Math.min(a, b)`
+    Min,
+    /// `Math.max(a, b)`
+    Max,
+    /// The decimal representation of an integer, for template literals
+    IntToString,
+}
+
+fn var(name: &str) -> UntypedExpr {
+    UntypedExpr::Var {
+        location: no_span(),
+        name: name.to_string(),
+    }
+}
+
+fn int(value: i64) -> UntypedExpr {
+    UntypedExpr::Int {
+        location: no_span(),
+        value: value.to_string(),
+    }
+}
+
+fn bin_op(name: BinOp, left: UntypedExpr, right: UntypedExpr) -> UntypedExpr {
+    UntypedExpr::BinOp {
+        location: no_span(),
+        name,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+fn if_else(condition: UntypedExpr, body: UntypedExpr, final_else: UntypedExpr) -> UntypedExpr {
+    let location = no_span();
+
+    UntypedExpr::If {
+        location,
+        branches: vec1![IfBranch {
+            condition,
+            body,
+            location,
+        }],
+        final_else: Box::new(final_else),
+    }
+}
+
+fn call(name: &str, args: Vec<UntypedExpr>) -> UntypedExpr {
+    let location = no_span();
+    let arguments = args
+        .into_iter()
+        .map(|value| CallArg {
+            label: None,
+            location,
+            value,
+        })
+        .collect();
+
+    UntypedExpr::Call {
+        arguments,
+        fun: Box::new(var(name)),
+        location,
+    }
+}
+
+/// `builtin.name(args)`, a function of aiken's `aiken/builtin` module, which must be imported
+pub fn builtin(name: &str, args: Vec<UntypedExpr>) -> UntypedExpr {
+    let location = no_span();
+    let arguments = args
+        .into_iter()
+        .map(|value| CallArg {
+            label: None,
+            location,
+            value,
+        })
+        .collect();
+
+    UntypedExpr::Call {
+        arguments,
+        fun: Box::new(UntypedExpr::FieldAccess {
+            location,
+            label: name.to_string(),
+            container: Box::new(var("builtin")),
+        }),
+        location,
+    }
+}
+
+fn annotation(name: &str) -> Annotation {
+    Annotation::Constructor {
+        location: no_span(),
+        module: None,
+        name: name.to_string(),
+        arguments: vec![],
+    }
+}
+
+impl PreludeFn {
+    /// The name of the function in the generated module
+    pub fn name(&self) -> &'static str {
+        match self {
+            PreludeFn::Pow => "prelude_pow",
+            PreludeFn::Abs => "prelude_abs",
+            PreludeFn::Min => "prelude_min",
+            PreludeFn::Max => "prelude_max",
+            PreludeFn::IntToString => "prelude_int_to_string",
+        }
+    }
+
+    /// Whether the definition calls functions of `aiken/builtin`
+    pub fn uses_builtin(&self) -> bool {
+        matches!(self, PreludeFn::IntToString)
+    }
+
+    /// `name(arguments)`
+    pub fn call(&self, arguments: Vec<UntypedExpr>) -> UntypedExpr {
+        call(self.name(), arguments)
+    }
+
+    pub fn definition(&self) -> UntypedDefinition {
+        let (params, body, return_type) = match self {
+            // if exponent < 0 {
+            //   error("negative exponent")
+            // } else if exponent == 0 {
+            //   1
+            // } else {
+            //   let half = prelude_pow(base * base, exponent / 2)
+            //   if exponent % 2 == 0 { half } else { base * half }
+            // }
+            PreludeFn::Pow => {
+                let half = UntypedExpr::Assignment {
+                    location: no_span(),
+                    value: Box::new(self.call(vec![
+                        bin_op(BinOp::MultInt, var("base"), var("base")),
+                        bin_op(BinOp::DivInt, var("exponent"), int(2)),
+                    ])),
+                    pattern: UntypedPattern::Var {
+                        location: no_span(),
+                        name: "half".to_string(),
+                    },
+                    kind: AssignmentKind::Let,
+                    annotation: None,
+                };
+                let is_even = bin_op(
+                    BinOp::Eq,
+                    bin_op(BinOp::ModInt, var("exponent"), int(2)),
+                    int(0),
+                );
+                let odd = bin_op(BinOp::MultInt, var("base"), var("half"));
+                let recurse = UntypedExpr::Sequence {
+                    location: no_span(),
+                    expressions: vec![half, if_else(is_even, var("half"), odd)],
+                };
+
+                let body = if_else(
+                    bin_op(BinOp::LtInt, var("exponent"), int(0)),
+                    UntypedExpr::ErrorTerm {
+                        location: no_span(),
+                        label: Some("negative exponent".to_string()),
+                    },
+                    if_else(bin_op(BinOp::Eq, var("exponent"), int(0)), int(1), recurse),
+                );
+
+                (vec!["base", "exponent"], body, "Int")
+            }
+            // if x < 0 { 0 - x } else { x }
+            PreludeFn::Abs => {
+                let body = if_else(
+                    bin_op(BinOp::LtInt, var("x"), int(0)),
+                    bin_op(BinOp::SubInt, int(0), var("x")),
+                    var("x"),
+                );
+
+                (vec!["x"], body, "Int")
+            }
+            // if a <= b { a } else { b }
+            PreludeFn::Min => {
+                let body = if_else(
+                    bin_op(BinOp::LtEqInt, var("a"), var("b")),
+                    var("a"),
+                    var("b"),
+                );
+
+                (vec!["a", "b"], body, "Int")
+            }
+            // if a >= b { a } else { b }
+            PreludeFn::Max => {
+                let body = if_else(
+                    bin_op(BinOp::GtEqInt, var("a"), var("b")),
+                    var("a"),
+                    var("b"),
+                );
+
+                (vec!["a", "b"], body, "Int")
+            }
+            // if n < 0 {
+            //   builtin.append_string("-", prelude_int_to_string(0 - n))
+            // } else if n < 10 {
+            //   builtin.decode_utf8(builtin.cons_bytearray(n + 48, #""))
+            // } else {
+            //   builtin.append_string(
+            //     prelude_int_to_string(n / 10),
+            //     prelude_int_to_string(n % 10),
+            //   )
+            // }
+            PreludeFn::IntToString => {
+                let negative = builtin(
+                    "append_string",
+                    vec![
+                        UntypedExpr::String {
+                            location: no_span(),
+                            value: "-".to_string(),
+                        },
+                        self.call(vec![bin_op(BinOp::SubInt, int(0), var("n"))]),
+                    ],
+                );
+                // `48` is the code of `0`
+                let digit = builtin(
+                    "decode_utf8",
+                    vec![builtin(
+                        "cons_bytearray",
+                        vec![
+                            bin_op(BinOp::AddInt, var("n"), int(48)),
+                            UntypedExpr::ByteArray {
+                                location: no_span(),
+                                bytes: vec![],
+                            },
+                        ],
+                    )],
+                );
+                let digits = builtin(
+                    "append_string",
+                    vec![
+                        self.call(vec![bin_op(BinOp::DivInt, var("n"), int(10))]),
+                        self.call(vec![bin_op(BinOp::ModInt, var("n"), int(10))]),
+                    ],
+                );
+
+                let body = if_else(
+                    bin_op(BinOp::LtInt, var("n"), int(0)),
+                    negative,
+                    if_else(bin_op(BinOp::LtInt, var("n"), int(10)), digit, digits),
+                );
+
+                (vec!["n"], body, "String")
+            }
+        };
+
+        let arguments = params
+            .into_iter()
+            .map(|name| Arg {
+                arg_name: ArgName::Named {
+                    name: name.to_string(),
+                    location: no_span(),
+                },
+                location: no_span(),
+                annotation: Some(annotation("Int")),
+                tipo: (),
+            })
+            .collect();
+
+        UntypedDefinition::Fn(Function {
+            arguments,
+            body,
+            doc: None,
+            location: no_span(),
+            name: self.name().to_string(),
+            public: false,
+            return_annotation: Some(annotation(return_type)),
+            return_type: (),
+            end_position: 0,
+        })
+    }
+}
@@ -656,6 +656,19 @@ where
 
     // Note(christos): Making this public
     pub fn eval_scripts(&self, scripts: Vec<Script>, match_name: Option<String>) -> Vec<EvalInfo> {
+        self.eval_scripts_with_logs(scripts, match_name)
+            .into_iter()
+            .map(|(eval_info, _)| eval_info)
+            .collect()
+    }
+
+    /// Like [Self::eval_scripts], along with the logs of each script, which include the
+    /// labels of the `error`s that failed it.
+    pub fn eval_scripts_with_logs(
+        &self,
+        scripts: Vec<Script>,
+        match_name: Option<String>,
+    ) -> Vec<(EvalInfo, Vec<String>)> {
         // TODO: in the future we probably just want to be able to
         // tell the machine to not explode on budget consumption.
         let initial_budget = ExBudget {
@@ -673,7 +686,7 @@ where
             }
 
             match script.program.eval(initial_budget) {
-                (Ok(result), remaining_budget, _) => {
+                (Ok(result), remaining_budget, logs) => {
                     let eval_info = EvalInfo {
                         success: result != Term::Error
                             && result != Term::Constant(Constant::Bool(false)),
@@ -682,9 +695,9 @@ where
                         output: Some(result),
                     };
 
-                    results.push(eval_info);
+                    results.push((eval_info, logs));
                 }
-                (Err(..), remaining_budget, _) => {
+                (Err(..), remaining_budget, logs) => {
                     let eval_info = EvalInfo {
                         success: false,
                         script,
@@ -692,7 +705,7 @@ where
                         output: None,
                     };
 
-                    results.push(eval_info);
+                    results.push((eval_info, logs));
                 }
             }
         }
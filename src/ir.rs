@@ -2,26 +2,33 @@ use std::fmt::Debug;
 
 use num_bigint::BigInt;
 use serde::{Deserialize, Serialize};
+use swc_core::common::Span;
 
 use ir_type::IRTy;
 
+use crate::ir_free_vars::FreeVars;
 use crate::ir_type;
 use crate::ir_type::{IRFunTy, TypeOf};
+use crate::ir_visitor::IRVisitor;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IRIdent {
     pub ident: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum IRLiteral {
     Boolean(bool),
     Float64(f64),
     BigInt(BigInt),
     String(String),
+    /// `` hex`deadbeef` ``
+    ByteArray(Vec<u8>),
+    /// `undefined`, the absence of a value of an optional type
+    Undefined,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IRNameTy {
     #[serde(flatten)]
     pub name: Box<IRIdent>,
@@ -34,17 +41,121 @@ impl TypeOf for IRNameTy {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// How the arguments of a call are matched with the parameters of a function,
+/// besides one argument for each parameter
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct IRArity {
+    /// The default value of each parameter, if any, e.g. `fee: bigint = 1n`
+    pub defaults: Vec<Option<IRExpr>>,
+    /// Whether the last parameter collects the remaining arguments in a list, `...xs: bigint[]`
+    pub rest: bool,
+}
+
+impl IRArity {
+    /// Whether every call must pass exactly one argument per parameter
+    pub fn is_fixed(&self) -> bool {
+        !self.rest && self.defaults.iter().all(Option::is_none)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IRFunDef {
     #[serde(flatten)]
     pub name: Box<IRIdent>,
     pub params: Vec<IRNameTy>,
+    pub arity: IRArity,
     pub fun_ty: IRFunTy,
     #[serde(flatten)]
     pub body: Box<IRBlockStmt>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A constructor of a user-defined type, along with its fields
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRConstructor {
+    #[serde(flatten)]
+    pub name: Box<IRIdent>,
+    /// The value of the discriminant for this constructor, if the type is a tagged union
+    pub tag: Option<String>,
+    pub fields: Vec<IRNameTy>,
+}
+
+impl IRConstructor {
+    /// Whether `field` is an optional property, which object literals may leave out
+    pub fn is_optional_field(&self, field: &str) -> bool {
+        self.fields
+            .iter()
+            .any(|f| f.name.ident == field && matches!(f.ty, IRTy::OptionTy(_)))
+    }
+}
+
+/// A user-defined type, e.g. `interface Datum { owner: string; deadline: bigint }`.
+/// A record type has exactly one constructor, named after the type.
+/// A tagged union, e.g. `type Action = { kind: "Claim" } | { kind: "Cancel"; by: string }`,
+/// has one constructor per member.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRTypeDef {
+    #[serde(flatten)]
+    pub name: Box<IRIdent>,
+    /// The type parameters of a generic type, e.g. `T` in `interface Box<T> { value: T }`,
+    /// named as the aiken type variables they become
+    pub type_params: Vec<String>,
+    /// The property that tells the members of a tagged union apart, e.g. `kind`
+    pub discriminant: Option<String>,
+    pub constructors: Vec<IRConstructor>,
+}
+
+impl IRTypeDef {
+    /// A record type, whose only constructor is named after the type
+    pub fn record(name: IRIdent, fields: Vec<IRNameTy>) -> Self {
+        let constructor = IRConstructor {
+            name: Box::new(name.clone()),
+            tag: None,
+            fields,
+        };
+
+        IRTypeDef {
+            name: Box::new(name),
+            type_params: vec![],
+            discriminant: None,
+            constructors: vec![constructor],
+        }
+    }
+
+    /// The type applied to `type_args`, whose fields have the type arguments in place of
+    /// the type parameters
+    pub fn instantiate(&self, type_args: &[IRTy]) -> IRTypeDef {
+        let mut typedef = self.clone();
+        for constructor in &mut typedef.constructors {
+            for field in &mut constructor.fields {
+                field.ty = field.ty.instantiate(&self.type_params, type_args);
+            }
+        }
+
+        typedef
+    }
+
+    /// The type of a value built with `constructor` from fields of the types `field_tys`,
+    /// in the order of the fields. Type arguments are those of the fields they occur in.
+    pub fn constructed_ty(&self, constructor: &IRConstructor, field_tys: &[IRTy]) -> IRTy {
+        let type_args = self.type_params.iter().cloned().map(IRTy::VarTy).collect();
+        let fun_ty = IRFunTy {
+            type_params: self.type_params.clone(),
+            param_tys: constructor.fields.iter().map(|f| f.ty.clone()).collect(),
+            ret_ty: Box::new(IRTy::NamedTy(self.name.ident.clone(), type_args)),
+        };
+
+        fun_ty.instantiate_ret_ty(field_tys)
+    }
+
+    /// The constructor of a tagged union for the given value of the discriminant
+    pub fn constructor_of_tag(&self, tag: &str) -> Option<&IRConstructor> {
+        self.constructors
+            .iter()
+            .find(|constructor| constructor.tag.as_deref() == Some(tag))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IRVarDef {
     #[serde(flatten)]
     pub name: Box<IRIdent>,
@@ -53,11 +164,60 @@ pub struct IRVarDef {
     pub value: Box<IRExpr>,
 }
 
+/// A field of an object pattern, `owner` or `owner: pattern`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRPropPattern {
+    #[serde(flatten)]
+    pub name: Box<IRIdent>,
+    pub pattern: IRPattern,
+}
+
+/// Destructuring pattern
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum IRPattern {
+    /// `x`, which binds the whole value
+    Var(IRIdent),
+    /// A hole in an array pattern, `[, b]`
+    Discard,
+    /// `{ owner, deadline: d }`
+    Object(Vec<IRPropPattern>),
+    /// `[a, b]` or `[head, ...tail]`
+    Array {
+        elements: Vec<IRPattern>,
+        rest: Option<Box<IRPattern>>,
+    },
+}
+
+impl IRPattern {
+    /// The variables bound by the pattern
+    pub fn vars(&self) -> Vec<&IRIdent> {
+        match self {
+            IRPattern::Var(ident) => vec![ident],
+            IRPattern::Discard => vec![],
+            IRPattern::Object(props) => props.iter().flat_map(|p| p.pattern.vars()).collect(),
+            IRPattern::Array { elements, rest } => elements
+                .iter()
+                .chain(rest.as_deref())
+                .flat_map(IRPattern::vars)
+                .collect(),
+        }
+    }
+}
+
+/// Variable definition with a destructuring pattern, `const { owner, deadline } = datum`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRPatDef {
+    pub pattern: IRPattern,
+    pub ty: IRTy,
+    pub is_mutable: bool,
+    pub value: Box<IRExpr>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum IRBinOp {
-    /// `==`
+    /// `==` or `===`
     EqEq,
-    /// `!=`
+    /// `!=` or `!==`
     NotEq,
     /// `<`
     Lt,
@@ -78,6 +238,10 @@ pub enum IRBinOp {
     Div,
     /// `%`
     Mod,
+    /// `**`
+    Exp,
+    /// `??`, the value of an optional type or a default
+    NullishCoalescing,
 
     /// `||`
     LogicalOr,
@@ -86,22 +250,141 @@ pub enum IRBinOp {
     LogicalAnd,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum IRUnOp {
+    /// `!`
+    Not,
+    /// `-`
+    Minus,
+    /// `+`
+    Plus,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRUnaryExpr {
+    pub op: IRUnOp,
+    pub expr: Box<IRExpr>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IRBinaryExpr {
     pub op: IRBinOp,
     pub left: Box<IRExpr>,
     pub right: Box<IRExpr>,
 }
 
+/// A property of an object literal, `name: value`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRProp {
+    #[serde(flatten)]
+    pub name: Box<IRIdent>,
+    pub value: IRExpr,
+}
+
+/// Object literal, `{ owner: "me", deadline: 42n }`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRObjectLit {
+    pub props: Vec<IRProp>,
+}
+
+/// Property access, `object.prop`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRMember {
+    pub object: Box<IRExpr>,
+    pub prop: Box<IRIdent>,
+}
+
+/// Array literal, `[1n, 2n, 3n]`, which is a list or a tuple depending on its type
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRArrayLit {
+    pub elements: Vec<IRExpr>,
+}
+
+/// Indexing with a constant, `pair[0]`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRIndex {
+    pub object: Box<IRExpr>,
+    pub index: usize,
+}
+
 /// Function application
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IRApply {
     #[serde(flatten)]
     pub name: Box<IRIdent>,
     pub args: Vec<IRExpr>,
+    /// The list passed as `...list` after the other arguments
+    pub spread: Option<Box<IRExpr>>,
+}
+
+/// Template literal, `` `owner: ${name}` ``, where the strings in `quasis` surround
+/// the interpolated `exprs`, so that there is one more quasi than there are expressions
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRTemplate {
+    pub quasis: Vec<String>,
+    pub exprs: Vec<IRExpr>,
+}
+
+/// The functions of the JS standard library that have an on-chain equivalent
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum IRBuiltin {
+    /// `Math.abs(x)`
+    MathAbs,
+    /// `Math.min(a, b, ...)`
+    MathMin,
+    /// `Math.max(a, b, ...)`
+    MathMax,
+    /// `Math.pow(base, exponent)`
+    MathPow,
+    /// `bytes.slice(start, end)` on byte arrays, the receiver being the first argument
+    Slice,
+    /// `bytes.concat(other)` on byte arrays, the receiver being the first argument
+    Concat,
+}
+
+/// Application of a function of the standard library, e.g. `Math.max(a, b)`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRBuiltinCall {
+    pub builtin: IRBuiltin,
+    pub args: Vec<IRExpr>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Anonymous function, e.g. `(a: number) => a + 1` or `function (x) { ... }`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRLambda {
+    pub params: Vec<IRNameTy>,
+    pub arity: IRArity,
+    pub fun_ty: IRFunTy,
+    /// The variables of the enclosing scopes that are referenced in the body
+    pub captures: Vec<IRIdent>,
+    #[serde(flatten)]
+    pub body: Box<IRBlockStmt>,
+}
+
+impl IRLambda {
+    /// Creates a lambda, computing its captured variables from the body.
+    pub fn new(params: Vec<IRNameTy>, arity: IRArity, fun_ty: IRFunTy, body: IRBlockStmt) -> Self {
+        let mut captures = FreeVars::default().visit_blockstmt(&body);
+        for param in &params {
+            captures.remove(&param.name.ident);
+        }
+
+        let captures = captures
+            .into_iter()
+            .map(|ident| IRIdent { ident })
+            .collect();
+
+        IRLambda {
+            params,
+            arity,
+            fun_ty,
+            captures,
+            body: Box::new(body),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IRIfStmt {
     #[serde(rename = "if")]
     pub _if: Box<IRExpr>,
@@ -111,50 +394,189 @@ pub struct IRIfStmt {
     pub _else: Box<IR>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Conditional expression, `c ? a : b`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRCondExpr {
+    #[serde(rename = "if")]
+    pub _if: Box<IRExpr>,
+    #[serde(rename = "then")]
+    pub _then: Box<IRExpr>,
+    #[serde(rename = "else")]
+    pub _else: Box<IRExpr>,
+}
+
+/// One or more `case`s of a `switch` that share a body.
+/// There are no tests for `default`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRSwitchCase {
+    pub tests: Vec<IRExpr>,
+    #[serde(flatten)]
+    pub body: IRBlockStmt,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRSwitchStmt {
+    pub discriminant: Box<IRExpr>,
+    pub cases: Vec<IRSwitchCase>,
+    /// Where the `switch` is in the source, for the errors about its cases
+    pub span: Span,
+}
+
+/// Assignment to a variable, `x = value`.
+/// Compound assignments are desugared, e.g. `x += 1` becomes `x = x + 1`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRAssignStmt {
+    #[serde(flatten)]
+    pub name: Box<IRIdent>,
+    pub value: IRExpr,
+}
+
+/// `for (const item of list) { ... }`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRForOfStmt {
+    pub item: Box<IRIdent>,
+    pub list: Box<IRExpr>,
+    #[serde(flatten)]
+    pub body: IRBlockStmt,
+}
+
+/// `while (test) { ... }`, C-style `for` loops are desugared to it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRWhileStmt {
+    pub test: Box<IRExpr>,
+    #[serde(flatten)]
+    pub body: IRBlockStmt,
+}
+
+/// The statements that follow a statement whose branches may return, as a function
+/// `name(vars)` that the branches call instead of getting a copy of them each, see
+/// [crate::ir_normalize]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRContinuationDef {
+    #[serde(flatten)]
+    pub name: Box<IRIdent>,
+    /// The variables that the branches may assign to, which the continuation is given
+    pub vars: Vec<IRIdent>,
+    #[serde(flatten)]
+    pub body: IRBlockStmt,
+}
+
+/// An `if` or `switch` statement, or a block, that assigns to `vars`, whose new values are
+/// merged when the branches join again, or bound after the block, see [crate::ir_ssa]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRJoinStmt {
+    pub vars: Vec<IRIdent>,
+    pub stmt: Box<IR>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IRExprStmt {
     #[serde(flatten)]
     pub expr: IRExpr,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IRReturnStmt {
     #[serde(flatten)]
     pub expr: Option<IRExpr>,
 }
+
+/// `throw new Error("message")`, which fails the script with the message in its logs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRThrowStmt {
+    pub message: Option<String>,
+}
+
+/// `console.log(...)`, whose arguments are joined into a single string `message`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRTraceStmt {
+    pub message: IRExpr,
+}
 /// Expressions
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum IRExpr {
     Literal(IRLiteral),
     Identifier(IRIdent),
+    Unary(Box<IRUnaryExpr>),
     Binary(Box<IRBinaryExpr>),
+    Cond(Box<IRCondExpr>),
+    Object(Box<IRObjectLit>),
+    Member(Box<IRMember>),
+    /// `object?.prop`, where `object` has an optional type
+    OptMember(Box<IRMember>),
+    Array(Box<IRArrayLit>),
+    Index(Box<IRIndex>),
     Apply(Box<IRApply>),
+    Builtin(Box<IRBuiltinCall>),
+    Template(Box<IRTemplate>),
+    Lambda(Box<IRLambda>),
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+/// One name brought into scope by an `import`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRImportSpecifier {
+    #[serde(flatten)]
+    pub name: Box<IRIdent>,
+    pub alias: Option<IRIdent>,
+}
+
+/// `import { a, b as c } from "./module"`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IRImport {
+    pub specifiers: Vec<IRImportSpecifier>,
+    /// The module specifier, exactly as written in the source
+    pub src: String,
+    /// The name of the module that `src` resolves to.
+    /// This is filled in by the driver, once the imported file has been located.
+    pub module: Option<String>,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct IRBlockStmt {
     pub body: Vec<IR>,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct IRScript {
     pub body: Vec<IR>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum IR {
     /// A program, module, etc.
     Script(IRScript),
+    /// `import` declaration
+    Import(IRImport),
     /// `function` definition
     FunDef(IRFunDef),
     /// variable definition
     VarDef(IRVarDef),
+    /// variable definitions with a destructuring pattern
+    PatDef(IRPatDef),
+    /// `interface` or `type` definition
+    TypeDef(IRTypeDef),
     /// `return` statement
     ReturnStmt(IRReturnStmt),
+    /// `throw` statement
+    ThrowStmt(IRThrowStmt),
+    /// `console.log` statement
+    TraceStmt(IRTraceStmt),
     /// Block of statements and expressions
     BlockStmt(IRBlockStmt),
     /// `if`/`then`/`else` statement
     IfStmt(IRIfStmt),
+    /// `switch` statement
+    SwitchStmt(IRSwitchStmt),
+    /// Assignment to a variable
+    AssignStmt(IRAssignStmt),
+    /// `for...of` loop
+    ForOfStmt(IRForOfStmt),
+    /// `while` loop
+    WhileStmt(IRWhileStmt),
+    /// Branches that assign to variables
+    JoinStmt(IRJoinStmt),
+    /// What follows a statement whose branches may return, shared by the branches
+    ContinuationDef(IRContinuationDef),
     ///
     ExprStmt(IRExprStmt),
     /// Expression
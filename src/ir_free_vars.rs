@@ -0,0 +1,307 @@
+//! Computes the free variables of our IR, that is the identifiers that are used
+//! but not bound by the IR fragment itself, and all the names it uses, so that
+//! the names we generate do not clash with them.
+
+use std::collections::BTreeSet;
+
+use crate::ir::*;
+use crate::ir_visitor::IRVisitor;
+
+pub type Vars = BTreeSet<String>;
+
+#[derive(Default)]
+pub struct FreeVars {
+    /// Whether the names bound by the IR fragment are collected too, see [names]
+    with_bound: bool,
+}
+
+/// All the names used by `irs`, be they bound by them or not.
+pub fn names(irs: &[IR]) -> Vars {
+    FreeVars { with_bound: true }.visit_all(irs)
+}
+
+/// `base`, followed by as many `_` as it takes for it not to be one of `used`.
+pub fn fresh_name(base: &str, used: &Vars) -> String {
+    let mut name = base.to_string();
+    while used.contains(&name) {
+        name.push('_');
+    }
+
+    name
+}
+
+impl FreeVars {
+    pub fn visit_all<'a>(&self, irs: impl IntoIterator<Item = &'a IR>) -> Vars {
+        // A definition is visible by everything that follows it
+        let mut bound = Vars::new();
+        let mut free = Vars::new();
+
+        for ir in irs {
+            let vars = self.visit_ir(ir);
+            free.extend(vars.difference(&bound).cloned());
+
+            match ir {
+                IR::VarDef(vardef) => {
+                    bound.insert(vardef.name.ident.clone());
+                }
+                IR::PatDef(patdef) => {
+                    bound.extend(patdef.pattern.vars().into_iter().map(|v| v.ident.clone()));
+                }
+                IR::FunDef(fundef) => {
+                    bound.insert(fundef.name.ident.clone());
+                }
+                IR::ContinuationDef(contdef) => {
+                    bound.insert(contdef.name.ident.clone());
+                }
+                IR::Import(import) => {
+                    for specifier in &import.specifiers {
+                        let local = specifier.alias.as_ref().unwrap_or(&specifier.name);
+                        bound.insert(local.ident.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if self.with_bound {
+            free.extend(bound);
+        }
+
+        free
+    }
+
+    /// Removes the names bound by a function from the variables of its body, unless we are
+    /// collecting all the names.
+    fn unbind<'a>(&self, vars: &mut Vars, bound: impl IntoIterator<Item = &'a String>) {
+        for name in bound {
+            if self.with_bound {
+                vars.insert(name.clone());
+            } else {
+                vars.remove(name);
+            }
+        }
+    }
+}
+
+impl IRVisitor<Vars> for FreeVars {
+    fn new() -> Self {
+        FreeVars::default()
+    }
+
+    fn visit_script(&self, script: &IRScript) -> Vars {
+        self.visit_all(&script.body)
+    }
+
+    fn visit_import(&self, import: &IRImport) -> Vars {
+        Vars::new()
+    }
+
+    fn visit_fundef(&self, fundef: &IRFunDef) -> Vars {
+        let mut vars = self.visit_blockstmt(&fundef.body);
+        self.unbind(
+            &mut vars,
+            fundef.params.iter().map(|param| &param.name.ident),
+        );
+        // recursive calls
+        self.unbind(&mut vars, [&fundef.name.ident]);
+
+        vars
+    }
+
+    fn visit_vardef(&self, vardef: &IRVarDef) -> Vars {
+        self.visit_expr(&vardef.value)
+    }
+
+    fn visit_patdef(&self, patdef: &IRPatDef) -> Vars {
+        self.visit_expr(&patdef.value)
+    }
+
+    fn visit_typedef(&self, typedef: &IRTypeDef) -> Vars {
+        Vars::new()
+    }
+
+    fn visit_retstmt(&self, retstmt: &IRReturnStmt) -> Vars {
+        match &retstmt.expr {
+            None => Vars::new(),
+            Some(expr) => self.visit_expr(expr),
+        }
+    }
+
+    fn visit_throwstmt(&self, throwstmt: &IRThrowStmt) -> Vars {
+        Vars::new()
+    }
+
+    fn visit_tracestmt(&self, tracestmt: &IRTraceStmt) -> Vars {
+        self.visit_expr(&tracestmt.message)
+    }
+
+    fn visit_blockstmt(&self, blockstmt: &IRBlockStmt) -> Vars {
+        self.visit_all(&blockstmt.body)
+    }
+
+    fn visit_ifstmt(&self, ifstmt: &IRIfStmt) -> Vars {
+        let mut vars = self.visit_expr(&ifstmt._if);
+        vars.extend(self.visit_ir(&ifstmt._then));
+        vars.extend(self.visit_ir(&ifstmt._else));
+
+        vars
+    }
+
+    fn visit_switchstmt(&self, switchstmt: &IRSwitchStmt) -> Vars {
+        let mut vars = self.visit_expr(&switchstmt.discriminant);
+        for case in &switchstmt.cases {
+            for test in &case.tests {
+                vars.extend(self.visit_expr(test));
+            }
+            vars.extend(self.visit_blockstmt(&case.body));
+        }
+
+        vars
+    }
+
+    fn visit_assignstmt(&self, assignstmt: &IRAssignStmt) -> Vars {
+        // The variable must have been defined elsewhere
+        let mut vars = self.visit_expr(&assignstmt.value);
+        vars.insert(assignstmt.name.ident.clone());
+
+        vars
+    }
+
+    fn visit_forofstmt(&self, forofstmt: &IRForOfStmt) -> Vars {
+        let mut vars = self.visit_blockstmt(&forofstmt.body);
+        self.unbind(&mut vars, [&forofstmt.item.ident]);
+        vars.extend(self.visit_expr(&forofstmt.list));
+
+        vars
+    }
+
+    fn visit_whilestmt(&self, whilestmt: &IRWhileStmt) -> Vars {
+        let mut vars = self.visit_expr(&whilestmt.test);
+        vars.extend(self.visit_blockstmt(&whilestmt.body));
+
+        vars
+    }
+
+    fn visit_joinstmt(&self, joinstmt: &IRJoinStmt) -> Vars {
+        let mut vars = self.visit_ir(&joinstmt.stmt);
+        vars.extend(joinstmt.vars.iter().map(|var| var.ident.clone()));
+
+        vars
+    }
+
+    fn visit_continuationdef(&self, contdef: &IRContinuationDef) -> Vars {
+        let mut vars = self.visit_blockstmt(&contdef.body);
+        self.unbind(&mut vars, contdef.vars.iter().map(|var| &var.ident));
+
+        vars
+    }
+
+    fn visit_exprstmt(&self, exprstmt: &IRExprStmt) -> Vars {
+        self.visit_expr(&exprstmt.expr)
+    }
+
+    fn visit_literal(&self, literal: &IRLiteral) -> Vars {
+        Vars::new()
+    }
+
+    fn visit_ident(&self, ident: &IRIdent) -> Vars {
+        Vars::from([ident.ident.clone()])
+    }
+
+    fn visit_unary_op(&self, unary_op: &IRUnaryExpr) -> Vars {
+        self.visit_expr(&unary_op.expr)
+    }
+
+    fn visit_binary_op(&self, binary_op: &IRBinaryExpr) -> Vars {
+        let mut vars = self.visit_expr(&binary_op.left);
+        vars.extend(self.visit_expr(&binary_op.right));
+
+        vars
+    }
+
+    fn visit_cond(&self, cond: &IRCondExpr) -> Vars {
+        let mut vars = self.visit_expr(&cond._if);
+        vars.extend(self.visit_expr(&cond._then));
+        vars.extend(self.visit_expr(&cond._else));
+
+        vars
+    }
+
+    fn visit_object(&self, object: &IRObjectLit) -> Vars {
+        let mut vars = Vars::new();
+        for prop in &object.props {
+            vars.extend(self.visit_expr(&prop.value));
+        }
+
+        vars
+    }
+
+    fn visit_member(&self, member: &IRMember) -> Vars {
+        self.visit_expr(&member.object)
+    }
+
+    fn visit_opt_member(&self, member: &IRMember) -> Vars {
+        self.visit_expr(&member.object)
+    }
+
+    fn visit_array(&self, array: &IRArrayLit) -> Vars {
+        let mut vars = Vars::new();
+        for element in &array.elements {
+            vars.extend(self.visit_expr(element));
+        }
+
+        vars
+    }
+
+    fn visit_index(&self, index: &IRIndex) -> Vars {
+        self.visit_expr(&index.object)
+    }
+
+    fn visit_apply(&self, apply: &IRApply) -> Vars {
+        let mut vars = Vars::from([apply.name.ident.clone()]);
+        for arg in &apply.args {
+            vars.extend(self.visit_expr(arg));
+        }
+        if let Some(spread) = &apply.spread {
+            vars.extend(self.visit_expr(spread));
+        }
+
+        vars
+    }
+
+    fn visit_builtin_call(&self, builtin_call: &IRBuiltinCall) -> Vars {
+        let mut vars = Vars::new();
+        for arg in &builtin_call.args {
+            vars.extend(self.visit_expr(arg));
+        }
+
+        vars
+    }
+
+    fn visit_template(&self, template: &IRTemplate) -> Vars {
+        let mut vars = Vars::new();
+        for expr in &template.exprs {
+            vars.extend(self.visit_expr(expr));
+        }
+
+        vars
+    }
+
+    fn visit_lambda(&self, lambda: &IRLambda) -> Vars {
+        if self.with_bound {
+            let mut vars = self.visit_blockstmt(&lambda.body);
+            self.unbind(
+                &mut vars,
+                lambda.params.iter().map(|param| &param.name.ident),
+            );
+            return vars;
+        }
+
+        // Already computed when the lambda was created
+        lambda
+            .captures
+            .iter()
+            .map(|capture| capture.ident.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,354 @@
+//! Control-flow normalisation of function bodies.
+//!
+//! Aiken is expression-oriented, so a function body is a single expression and there is
+//! no such thing as returning early. We rewrite statement-oriented bodies so that every
+//! `return` ends up in tail position, by moving whatever follows an `if` that may return
+//! into its branches. For instance
+//!
+//! ```js
+//! if (!ok) return false;
+//! let b = a + 1;
+//! return b > 0;
+//! ```
+//!
+//! becomes
+//!
+//! ```js
+//! if (!ok) { return false; } else { let b = a + 1; return b > 0; }
+//! ```
+//!
+//! A `throw` ends the function just like a `return`, so it is moved to tail position too.
+//!
+//! When more than one branch carries on with what follows, the continuation is bound once,
+//! as a function that the branches call, instead of being copied into each of them, which
+//! would make the size of the script grow exponentially with the number of such statements:
+//!
+//! ```js
+//! if (a) { if (b) return 0; fee = 2; } else { fee = 3; }
+//! let c = fee + 1;
+//! return c;
+//! ```
+//!
+//! becomes
+//!
+//! ```js
+//! const continuation = (fee) => { let c = fee + 1; return c; };
+//! if (a) { if (b) { return 0; } else { fee = 2; return continuation(fee); } }
+//! else { fee = 3; return continuation(fee); }
+//! ```
+//!
+//! The continuation is given the variables that the branches assign to, since their new
+//! values are only visible within the branches. A block is closed the same way when its
+//! definitions shadow variables that the continuation uses.
+
+use crate::ir::*;
+use crate::ir_free_vars::{fresh_name, names, FreeVars, Vars};
+use crate::ir_ssa::assigned_vars;
+use crate::ir_visitor::IRVisitor;
+
+/// Normalises the body of a function, see the [module documentation](self).
+pub fn normalize_body(body: IRBlockStmt) -> IRBlockStmt {
+    IRBlockStmt {
+        body: normalize_stmts(body.body),
+    }
+}
+
+fn normalize_stmts(stmts: Vec<IR>) -> Vec<IR> {
+    let mut normalized = Vec::with_capacity(stmts.len());
+    let mut stmts = stmts.into_iter();
+
+    while let Some(stmt) = stmts.next() {
+        if may_return(&stmt) {
+            // The rest of the statements become the continuation of this one
+            let rest: Vec<IR> = stmts.collect();
+            normalized.extend(normalize_with_continuation(stmt, rest));
+            break;
+        }
+
+        normalized.push(stmt);
+    }
+
+    normalized
+}
+
+/// Normalises `stmt`, which may return, followed by `rest`.
+fn normalize_with_continuation(stmt: IR, rest: Vec<IR>) -> Vec<IR> {
+    let shared = match &stmt {
+        IR::IfStmt(ifstmt) => [&ifstmt._then, &ifstmt._else]
+            .into_iter()
+            .filter(|branch| !always_returns(branch))
+            .count(),
+        IR::SwitchStmt(switchstmt) => switchstmt
+            .cases
+            .iter()
+            .filter(|case| !case.body.body.iter().any(always_returns))
+            .count(),
+        IR::BlockStmt(blockstmt) => {
+            // The block can simply be merged with what follows, unless that would make its
+            // definitions visible to the continuation
+            let free = FreeVars::default().visit_all(&rest);
+            if declared_vars(&blockstmt.body).is_disjoint(&free) {
+                let mut body = blockstmt.body.clone();
+                body.extend(rest);
+                return normalize_stmts(body);
+            }
+            1
+        }
+        // Anything after a `return` is dead code
+        _ => return vec![stmt],
+    };
+
+    let is_small = matches!(rest.as_slice(), [] | [IR::ReturnStmt(_) | IR::ThrowStmt(_)]);
+    if !matches!(stmt, IR::BlockStmt(_)) && (shared < 2 || is_small) {
+        return vec![map_branches(stmt, &mut |branch| {
+            append_continuation(branch, &rest)
+        })];
+    }
+
+    // `const continuation = (vars) => { rest }`, with a name that none of the statements use
+    let mut used = names(&rest);
+    used.extend(names(std::slice::from_ref(&stmt)));
+    let name = IRIdent {
+        ident: fresh_name("continuation", &used),
+    };
+
+    let free = FreeVars::default().visit_all(&rest);
+    let vars: Vec<IRIdent> = assigned_vars(&stmt)
+        .into_iter()
+        .filter(|var| free.contains(var))
+        .map(|ident| IRIdent { ident })
+        .collect();
+
+    let call = IR::ReturnStmt(IRReturnStmt {
+        expr: Some(IRExpr::Apply(Box::new(IRApply {
+            name: Box::new(name.clone()),
+            args: vars
+                .iter()
+                .map(|var| IRExpr::Identifier(var.clone()))
+                .collect(),
+            spread: None,
+        }))),
+    });
+    let contdef = IR::ContinuationDef(IRContinuationDef {
+        name: Box::new(name),
+        vars,
+        body: IRBlockStmt {
+            body: normalize_stmts(rest),
+        },
+    });
+
+    let stmt = map_branches(stmt, &mut |branch| {
+        append_continuation(branch, std::slice::from_ref(&call))
+    });
+
+    vec![contdef, stmt]
+}
+
+/// Replaces the branches of `stmt`, an `if`, a `switch` or a block, with their statements
+/// as given by `f`.
+fn map_branches(stmt: IR, f: &mut impl FnMut(IR) -> Vec<IR>) -> IR {
+    match stmt {
+        IR::IfStmt(IRIfStmt { _if, _then, _else }) => IR::IfStmt(IRIfStmt {
+            _if,
+            _then: Box::new(IR::BlockStmt(IRBlockStmt { body: f(*_then) })),
+            _else: Box::new(IR::BlockStmt(IRBlockStmt { body: f(*_else) })),
+        }),
+        IR::SwitchStmt(IRSwitchStmt {
+            discriminant,
+            cases,
+            span,
+        }) => IR::SwitchStmt(IRSwitchStmt {
+            discriminant,
+            span,
+            cases: cases
+                .into_iter()
+                .map(|IRSwitchCase { tests, body }| IRSwitchCase {
+                    tests,
+                    body: IRBlockStmt {
+                        body: f(IR::BlockStmt(body)),
+                    },
+                })
+                .collect(),
+        }),
+        IR::BlockStmt(block) => IR::BlockStmt(IRBlockStmt {
+            body: f(IR::BlockStmt(block)),
+        }),
+        stmt => stmt,
+    }
+}
+
+/// The variables defined by the statements of a block, which are local to it.
+fn declared_vars(stmts: &[IR]) -> Vars {
+    let mut vars = Vars::new();
+    for stmt in stmts {
+        match stmt {
+            IR::VarDef(vardef) => {
+                vars.insert(vardef.name.ident.clone());
+            }
+            IR::PatDef(patdef) => {
+                vars.extend(patdef.pattern.vars().into_iter().map(|v| v.ident.clone()));
+            }
+            IR::FunDef(fundef) => {
+                vars.insert(fundef.name.ident.clone());
+            }
+            _ => {}
+        }
+    }
+
+    vars
+}
+
+/// Appends `rest` to a branch, unless the branch always returns.
+fn append_continuation(branch: IR, rest: &[IR]) -> Vec<IR> {
+    let always = always_returns(&branch);
+    let mut stmts = into_stmts(branch);
+    if !always {
+        stmts.extend(rest.iter().cloned());
+    }
+
+    normalize_stmts(stmts)
+}
+
+fn into_stmts(ir: IR) -> Vec<IR> {
+    match ir {
+        IR::BlockStmt(IRBlockStmt { body }) => body,
+        ir => vec![ir],
+    }
+}
+
+/// Whether executing `ir` may end with a `return`, or a `throw`.
+pub fn may_return(ir: &IR) -> bool {
+    may_end_with(ir, &|ir| matches!(ir, IR::ReturnStmt(_) | IR::ThrowStmt(_)))
+}
+
+/// Whether executing `ir` may end with a `return`, as opposed to a `throw`.
+pub fn may_return_value(ir: &IR) -> bool {
+    may_end_with(ir, &|ir| matches!(ir, IR::ReturnStmt(_)))
+}
+
+fn may_end_with(ir: &IR, is_end: &impl Fn(&IR) -> bool) -> bool {
+    let any = |stmts: &[IR]| stmts.iter().any(|stmt| may_end_with(stmt, is_end));
+
+    match ir {
+        _ if is_end(ir) => true,
+        IR::BlockStmt(IRBlockStmt { body }) => any(body),
+        IR::IfStmt(ifstmt) => {
+            may_end_with(&ifstmt._then, is_end) || may_end_with(&ifstmt._else, is_end)
+        }
+        IR::SwitchStmt(switchstmt) => switchstmt.cases.iter().any(|case| any(&case.body.body)),
+        _ => false,
+    }
+}
+
+/// Whether executing `ir` always ends with a `return` or a `throw`.
+fn always_returns(ir: &IR) -> bool {
+    match ir {
+        IR::ReturnStmt(_) | IR::ThrowStmt(_) => true,
+        IR::BlockStmt(IRBlockStmt { body }) => body.iter().any(always_returns),
+        IR::IfStmt(ifstmt) => always_returns(&ifstmt._then) && always_returns(&ifstmt._else),
+        IR::SwitchStmt(switchstmt) => switchstmt
+            .cases
+            .iter()
+            .all(|case| case.body.body.iter().any(always_returns)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::js_to_ir::parse_stmts;
+
+    fn normalize(code: &str) -> Vec<IR> {
+        normalize_body(IRBlockStmt {
+            body: parse_stmts(code),
+        })
+        .body
+    }
+
+    fn stmts(branch: &IR) -> &[IR] {
+        match branch {
+            IR::BlockStmt(block) => &block.body,
+            ir => panic!("not a block: {:?}", ir),
+        }
+    }
+
+    #[test]
+    fn statements_after_an_early_return_move_to_the_else_branch() {
+        let body = normalize(
+            "if (!ok) return false;
+             let b = a + 1;
+             return b > 0;",
+        );
+
+        match body.as_slice() {
+            [IR::IfStmt(ifstmt)] => {
+                assert!(matches!(stmts(&ifstmt._then), [IR::ReturnStmt(_)]));
+                assert!(matches!(
+                    stmts(&ifstmt._else),
+                    [IR::VarDef(_), IR::ReturnStmt(_)]
+                ));
+            }
+            body => panic!("unexpected body: {:?}", body),
+        }
+    }
+
+    #[test]
+    fn dead_code_after_a_return_is_dropped() {
+        let body = normalize("return 1; return 2;");
+
+        assert!(matches!(body.as_slice(), [IR::ReturnStmt(_)]));
+    }
+
+    #[test]
+    fn a_continuation_shared_by_branches_is_bound_once() {
+        let body = normalize(
+            "if (a) { if (b) return 0; fee = 2; } else { fee = 3; }
+             let c = fee + 1;
+             return c;",
+        );
+
+        let (contdef, ifstmt) = match body.as_slice() {
+            [IR::ContinuationDef(contdef), IR::IfStmt(ifstmt)] => (contdef, ifstmt),
+            body => panic!("unexpected body: {:?}", body),
+        };
+        assert_eq!(contdef.name.ident, "continuation");
+        let vars: Vec<&str> = contdef.vars.iter().map(|v| v.ident.as_str()).collect();
+        assert_eq!(vars, ["fee"]);
+        assert!(matches!(
+            contdef.body.body.as_slice(),
+            [IR::VarDef(_), IR::ReturnStmt(_)]
+        ));
+
+        // Each branch ends by calling the continuation, rather than with a copy of it
+        let calls_continuation = |stmts: &[IR]| {
+            matches!(stmts.last(), Some(IR::ReturnStmt(IRReturnStmt {
+                expr: Some(IRExpr::Apply(apply)),
+            })) if apply.name.ident == "continuation")
+        };
+        assert!(calls_continuation(stmts(&ifstmt._else)));
+        match stmts(&ifstmt._then) {
+            [IR::IfStmt(inner)] => {
+                assert!(matches!(stmts(&inner._then), [IR::ReturnStmt(_)]));
+                assert!(calls_continuation(stmts(&inner._else)));
+            }
+            then => panic!("unexpected branch: {:?}", then),
+        }
+    }
+
+    #[test]
+    fn the_continuation_name_does_not_clash() {
+        let body = normalize(
+            "if (a) { if (b) return 0; fee = 2; } else { fee = 3; }
+             const continuation = fee + 1;
+             return continuation;",
+        );
+
+        match body.first() {
+            Some(IR::ContinuationDef(contdef)) => {
+                assert_eq!(contdef.name.ident, "continuation_")
+            }
+            first => panic!("unexpected statement: {:?}", first),
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! Assignments to mutable variables, in the spirit of SSA conversion.
+//!
+//! Aiken has no mutable variables, but it does have shadowing, so every assignment
+//! `x = x + 1` can become a new binding `let x = x + 1` that hides the previous one. This
+//! gives us SSA renaming for free, as long as the new binding is visible wherever the
+//! variable is used next. That is the case for assignments in sequence, but not for
+//! assignments inside the branches of an `if` or a `switch`, which must be merged when the
+//! branches join again. We mark those statements with the variables they assign to, so
+//! that each branch can yield the new values, which are then bound after the statement:
+//!
+//! ```js
+//! let fee = 1;
+//! if (big) { fee = 2; }
+//! return fee;
+//! ```
+//!
+//! becomes
+//!
+//! ```aiken
+//! let fee = 1
+//! let fee = if big { let fee = 2  fee } else { fee }
+//! fee
+//! ```
+//!
+//! A nested block has its own scope in aiken too, so it is joined in the same way, as
+//! `let fee = { let fee = 2  fee }`, when it assigns to the variables of the enclosing
+//! scopes. This is what makes `for (let i = 0; ..) { .. }`, which is a block with the
+//! declaration of `i` and a `while` loop, update the variables of the enclosing scopes.
+//!
+//! Loops are handled when they are lowered, by threading the variables they assign to
+//! through the generated helper functions.
+//!
+//! Along the way we reject assignments to `const`s and to variables that are not
+//! declared in the function, such as the variables captured by a closure.
+
+use std::collections::HashMap;
+
+use crate::ir::*;
+use crate::ir_free_vars::Vars;
+use crate::ir_normalize::may_return;
+use crate::js_to_ir::{IRError, IRResult};
+
+/// All the variables assigned to by `ir`, at any depth, that are declared outside of it.
+/// A variable declared in a nested block, which may shadow one of the enclosing scopes,
+/// is local to that block.
+pub fn assigned_vars(ir: &IR) -> Vars {
+    match ir {
+        IR::AssignStmt(assignstmt) => Vars::from([assignstmt.name.ident.clone()]),
+        IR::JoinStmt(joinstmt) => joinstmt.vars.iter().map(|var| var.ident.clone()).collect(),
+        IR::BlockStmt(blockstmt) => block_assigned_vars(&blockstmt.body),
+        IR::ForOfStmt(forofstmt) => {
+            let mut vars = block_assigned_vars(&forofstmt.body.body);
+            vars.remove(&forofstmt.item.ident);
+            vars
+        }
+        IR::WhileStmt(whilestmt) => block_assigned_vars(&whilestmt.body.body),
+        IR::IfStmt(ifstmt) => {
+            let mut vars = assigned_vars(&ifstmt._then);
+            vars.extend(assigned_vars(&ifstmt._else));
+            vars
+        }
+        IR::SwitchStmt(switchstmt) => switchstmt
+            .cases
+            .iter()
+            .flat_map(|case| block_assigned_vars(&case.body.body))
+            .collect(),
+        _ => Vars::new(),
+    }
+}
+
+/// The variables assigned to by the statements of a block that are declared outside of it,
+/// see [assigned_vars].
+pub fn block_assigned_vars(stmts: &[IR]) -> Vars {
+    let mut declared = Vars::new();
+    let mut vars = Vars::new();
+    for stmt in stmts {
+        vars.extend(
+            assigned_vars(stmt)
+                .into_iter()
+                .filter(|var| !declared.contains(var)),
+        );
+
+        match stmt {
+            IR::VarDef(vardef) => {
+                declared.insert(vardef.name.ident.clone());
+            }
+            IR::PatDef(patdef) => {
+                declared.extend(patdef.pattern.vars().into_iter().map(|v| v.ident.clone()));
+            }
+            IR::FunDef(fundef) => {
+                declared.insert(fundef.name.ident.clone());
+            }
+            _ => {}
+        }
+    }
+
+    vars
+}
+
+/// Whether `ir` traces messages, at any depth.
+pub fn traces(ir: &IR) -> bool {
+    let any = |irs: &[IR]| irs.iter().any(traces);
+
+    match ir {
+        IR::TraceStmt(_) => true,
+        IR::JoinStmt(joinstmt) => traces(&joinstmt.stmt),
+        IR::BlockStmt(blockstmt) => any(&blockstmt.body),
+        IR::ForOfStmt(forofstmt) => any(&forofstmt.body.body),
+        IR::WhileStmt(whilestmt) => any(&whilestmt.body.body),
+        IR::IfStmt(ifstmt) => traces(&ifstmt._then) || traces(&ifstmt._else),
+        IR::SwitchStmt(switchstmt) => switchstmt.cases.iter().any(|case| any(&case.body.body)),
+        _ => false,
+    }
+}
+
+/// Checks the assignments of a function body and marks the joins, see the
+/// [module documentation](self). The body must have been normalised already.
+pub fn ssa_body(params: &[IRNameTy], body: IRBlockStmt) -> IRResult<IRBlockStmt> {
+    let mut ssa = Ssa::default();
+
+    // Parameters can be reassigned in JS
+    ssa.scopes.push(
+        params
+            .iter()
+            .map(|param| (param.name.ident.clone(), true))
+            .collect(),
+    );
+
+    Ok(IRBlockStmt {
+        body: ssa.block(body.body)?,
+    })
+}
+
+#[derive(Default)]
+struct Ssa {
+    /// The variables in scope and whether they are mutable, innermost scope last
+    scopes: Vec<HashMap<String, bool>>,
+}
+
+impl Ssa {
+    fn declare(&mut self, name: &str, is_mutable: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), is_mutable);
+        }
+    }
+
+    fn is_mutable(&self, name: &str) -> Option<bool> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied()
+    }
+
+    fn block(&mut self, stmts: Vec<IR>) -> IRResult<Vec<IR>> {
+        self.scopes.push(HashMap::new());
+        let stmts = stmts.into_iter().map(|stmt| self.stmt(stmt)).collect();
+        self.scopes.pop();
+
+        stmts
+    }
+
+    fn block_stmt(&mut self, block: IRBlockStmt) -> IRResult<IRBlockStmt> {
+        Ok(IRBlockStmt {
+            body: self.block(block.body)?,
+        })
+    }
+
+    fn branch(&mut self, branch: IR) -> IRResult<IR> {
+        match branch {
+            IR::BlockStmt(block) => self.block_stmt(block).map(IR::BlockStmt),
+            branch => Ok(IR::BlockStmt(IRBlockStmt {
+                body: self.block(vec![branch])?,
+            })),
+        }
+    }
+
+    fn stmt(&mut self, stmt: IR) -> IRResult<IR> {
+        match stmt {
+            IR::VarDef(vardef) => {
+                self.declare(&vardef.name.ident, vardef.is_mutable);
+                Ok(IR::VarDef(vardef))
+            }
+            IR::PatDef(patdef) => {
+                for var in patdef.pattern.vars() {
+                    self.declare(&var.ident, patdef.is_mutable);
+                }
+                Ok(IR::PatDef(patdef))
+            }
+            IR::FunDef(fundef) => {
+                self.declare(&fundef.name.ident, false);
+                Ok(IR::FunDef(fundef))
+            }
+            IR::AssignStmt(assignstmt) => match self.is_mutable(&assignstmt.name.ident) {
+                Some(true) => Ok(IR::AssignStmt(assignstmt)),
+                Some(false) => Err(IRError::ConstReassignment(assignstmt.name.ident)),
+                None => Err(IRError::AssignmentToUndeclared(assignstmt.name.ident)),
+            },
+            IR::BlockStmt(block) => {
+                let block = self.block_stmt(block)?;
+                Ok(self.join(IR::BlockStmt(block)))
+            }
+            IR::IfStmt(IRIfStmt { _if, _then, _else }) => {
+                let ifstmt = IRIfStmt {
+                    _if,
+                    _then: Box::new(self.branch(*_then)?),
+                    _else: Box::new(self.branch(*_else)?),
+                };
+
+                Ok(self.join(IR::IfStmt(ifstmt)))
+            }
+            IR::SwitchStmt(IRSwitchStmt {
+                discriminant,
+                cases,
+                span,
+            }) => {
+                let mut ssa_cases = Vec::with_capacity(cases.len());
+                for IRSwitchCase { tests, body } in cases {
+                    let body = self.block_stmt(body)?;
+                    ssa_cases.push(IRSwitchCase { tests, body });
+                }
+
+                let switchstmt = IRSwitchStmt {
+                    discriminant,
+                    cases: ssa_cases,
+                    span,
+                };
+
+                Ok(self.join(IR::SwitchStmt(switchstmt)))
+            }
+            IR::ForOfStmt(IRForOfStmt { item, list, body }) => {
+                self.scopes.push(HashMap::new());
+                self.declare(&item.ident, false);
+                let body = self.block_stmt(body);
+                self.scopes.pop();
+
+                Ok(IR::ForOfStmt(IRForOfStmt {
+                    item,
+                    list,
+                    body: body?,
+                }))
+            }
+            IR::WhileStmt(IRWhileStmt { test, body }) => Ok(IR::WhileStmt(IRWhileStmt {
+                test,
+                body: self.block_stmt(body)?,
+            })),
+            // The continuation carries on in the enclosing scopes, its variables included
+            IR::ContinuationDef(IRContinuationDef { name, vars, body }) => {
+                let body = self.block_stmt(body)?;
+                self.declare(&name.ident, false);
+
+                Ok(IR::ContinuationDef(IRContinuationDef { name, vars, body }))
+            }
+            stmt => Ok(stmt),
+        }
+    }
+
+    /// Marks a branching statement, or a block, that assigns to variables of the enclosing scopes,
+    /// unless it returns, in which case whatever follows is already inside its branches.
+    /// A statement that only traces is marked too, with no variables, so that it is
+    /// evaluated for its traces.
+    fn join(&self, stmt: IR) -> IR {
+        if may_return(&stmt) {
+            return stmt;
+        }
+
+        let vars: Vec<IRIdent> = assigned_vars(&stmt)
+            .into_iter()
+            .filter(|var| self.is_mutable(var).is_some())
+            .map(|ident| IRIdent { ident })
+            .collect();
+
+        if vars.is_empty() && !traces(&stmt) {
+            stmt
+        } else {
+            IR::JoinStmt(IRJoinStmt {
+                vars,
+                stmt: Box::new(stmt),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir_normalize::normalize_body;
+    use crate::js_to_ir::parse_stmts;
+
+    fn ssa(code: &str) -> IRResult<Vec<IR>> {
+        let body = normalize_body(IRBlockStmt {
+            body: parse_stmts(code),
+        });
+
+        ssa_body(&[], body).map(|body| body.body)
+    }
+
+    fn joined_vars(ir: &IR) -> Vec<&str> {
+        match ir {
+            IR::JoinStmt(join) => join.vars.iter().map(|var| var.ident.as_str()).collect(),
+            ir => panic!("not a join: {:?}", ir),
+        }
+    }
+
+    #[test]
+    fn branches_that_assign_are_joined() {
+        let body = ssa("let fee = 1;
+             if (big) { fee = 2; }
+             return fee;")
+        .unwrap();
+
+        assert_eq!(body.len(), 3);
+        assert_eq!(joined_vars(&body[1]), ["fee"]);
+    }
+
+    #[test]
+    fn all_the_assigned_variables_are_joined() {
+        let body = ssa("let a = 1;
+             let b = 2;
+             switch (k) {
+                 case 1: a = 3; break;
+                 default: b = 4;
+             }
+             return a + b;")
+        .unwrap();
+
+        let mut vars = joined_vars(&body[2]);
+        vars.sort();
+        assert_eq!(vars, ["a", "b"]);
+    }
+
+    #[test]
+    fn variables_declared_in_a_branch_are_not_joined() {
+        let body = ssa("let x = 1;
+             if (c) { let x = 2; x = 3; }
+             return x;")
+        .unwrap();
+
+        assert!(matches!(body[1], IR::IfStmt(_)));
+    }
+
+    #[test]
+    fn nested_blocks_that_assign_are_joined() {
+        let body = ssa("let x = 1;
+             { const y = 2; x = y; }
+             return x;")
+        .unwrap();
+
+        assert_eq!(joined_vars(&body[1]), ["x"]);
+    }
+
+    #[test]
+    fn assignments_to_consts_are_rejected() {
+        let result = ssa("const x = 1; if (c) { x = 2; } return x;");
+
+        assert!(matches!(result, Err(IRError::ConstReassignment(x)) if x == "x"));
+    }
+
+    #[test]
+    fn assignments_to_undeclared_variables_are_rejected() {
+        let result = ssa("y = 2; return y;");
+
+        assert!(matches!(result, Err(IRError::AssignmentToUndeclared(y)) if y == "y"));
+    }
+}
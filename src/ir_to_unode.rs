@@ -1,38 +1,120 @@
 //! Transforms our IR to aiken's untyped trees: [UntypedDefinition] and [UntypedExpr].
 
 use aiken_lang::ast::{
-    Arg, ArgName, AssignmentKind, BinOp, Function, IfBranch, ModuleKind, Span, UntypedDefinition,
-    UntypedModule, UntypedPattern,
+    Annotation, Arg, ArgName, AssignmentKind, BinOp, CallArg, Clause, DataType, Function, IfBranch,
+    ModuleKind, RecordConstructor, RecordConstructorArg, Span, UnqualifiedImport,
+    UntypedDefinition, UntypedModule, UntypedPattern, Use,
 };
 use aiken_lang::expr::UntypedExpr;
+use aiken_project::module::VALIDATOR_NAMES;
+use num_bigint::Sign;
 use serde::{Deserialize, Serialize};
-use vec1::vec1;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, HashMap};
+use swc_core::common::Span as SourceSpan;
+use vec1::{vec1, Vec1};
 
+use crate::aiken_prelude::{self, PreludeFn};
 use crate::ir::*;
+use crate::ir_free_vars::{fresh_name, names, FreeVars, Vars};
+use crate::ir_normalize::{may_return, normalize_body};
+use crate::ir_ssa::block_assigned_vars;
+use crate::ir_type::{IRFunTy, IRTy, TypeEnv};
 use crate::ir_visitor::IRVisitor;
-use crate::program::Error;
+use crate::program::{CompilerOptions, Error, NumericSemantics, Traces, Truthiness};
 
 /// Note this is clearly a hack
-fn no_span() -> Span {
+pub(crate) fn no_span() -> Span {
     Span { start: 0, end: 0 }
 }
 
-fn ir_bin_op_to_bin_op(op: IRBinOp) -> BinOp {
-    // Note how the translation to Aiken assumes integer operations but JS has floats
+/// The aiken operator for `op` on integers, or on any type for equality.
+/// See [ModuleBuilderFromIR::visit_binary_op] for the other types, and for the operators
+/// that aiken does not have.
+fn ir_bin_op_to_bin_op(op: IRBinOp) -> Option<BinOp> {
     match op {
-        IRBinOp::EqEq => BinOp::Eq,
-        IRBinOp::NotEq => BinOp::NotEq,
-        IRBinOp::Lt => BinOp::LtEqInt,
-        IRBinOp::LtEq => BinOp::LtEqInt,
-        IRBinOp::Gt => BinOp::GtInt,
-        IRBinOp::GtEq => BinOp::GtEqInt,
-        IRBinOp::Add => BinOp::AddInt,
-        IRBinOp::Sub => BinOp::SubInt,
-        IRBinOp::Mul => BinOp::MultInt,
-        IRBinOp::Div => BinOp::DivInt,
-        IRBinOp::Mod => BinOp::ModInt,
-        IRBinOp::LogicalOr => BinOp::Or,
-        IRBinOp::LogicalAnd => BinOp::And,
+        IRBinOp::EqEq => Some(BinOp::Eq),
+        IRBinOp::NotEq => Some(BinOp::NotEq),
+        IRBinOp::Lt => Some(BinOp::LtInt),
+        IRBinOp::LtEq => Some(BinOp::LtEqInt),
+        IRBinOp::Gt => Some(BinOp::GtInt),
+        IRBinOp::GtEq => Some(BinOp::GtEqInt),
+        IRBinOp::Add => Some(BinOp::AddInt),
+        IRBinOp::Sub => Some(BinOp::SubInt),
+        IRBinOp::Mul => Some(BinOp::MultInt),
+        IRBinOp::Div => Some(BinOp::DivInt),
+        IRBinOp::Mod => Some(BinOp::ModInt),
+        IRBinOp::LogicalOr => Some(BinOp::Or),
+        IRBinOp::LogicalAnd => Some(BinOp::And),
+        IRBinOp::Exp | IRBinOp::NullishCoalescing => None,
+    }
+}
+
+/// The aiken type annotation for `ty`, if there is one.
+fn ir_ty_to_annotation(ty: &IRTy) -> Option<Annotation> {
+    let location = no_span();
+    let constructor = |name: &str| Annotation::Constructor {
+        location,
+        module: None,
+        name: name.to_string(),
+        arguments: vec![],
+    };
+
+    match ty {
+        IRTy::BooleanTy => Some(constructor("Bool")),
+        // Note how numbers are integers on-chain
+        IRTy::Float64Ty | IRTy::BigIntTy => Some(constructor("Int")),
+        IRTy::StringTy => Some(constructor("String")),
+        IRTy::ByteArrayTy => Some(constructor("ByteArray")),
+        IRTy::NamedTy(name, type_args) => Some(Annotation::Constructor {
+            location,
+            module: None,
+            name: name.to_string(),
+            arguments: type_args
+                .iter()
+                .map(ir_ty_to_annotation)
+                .collect::<Option<Vec<_>>>()?,
+        }),
+        // Already named as aiken type variables, and generic functions need no declaration
+        IRTy::VarTy(name) => Some(Annotation::Var {
+            location,
+            name: name.to_string(),
+        }),
+        IRTy::ListTy(elem_ty) => Some(Annotation::Constructor {
+            location,
+            module: None,
+            name: "List".to_string(),
+            arguments: vec![ir_ty_to_annotation(elem_ty)?],
+        }),
+        IRTy::OptionTy(ty) => Some(Annotation::Constructor {
+            location,
+            module: None,
+            name: "Option".to_string(),
+            arguments: vec![ir_ty_to_annotation(ty)?],
+        }),
+        IRTy::TupleTy(elem_tys) => {
+            let elems = elem_tys
+                .iter()
+                .map(ir_ty_to_annotation)
+                .collect::<Option<Vec<_>>>()?;
+
+            Some(Annotation::Tuple { location, elems })
+        }
+        IRTy::FunTy(fun_ty) => {
+            let arguments = fun_ty
+                .param_tys
+                .iter()
+                .map(ir_ty_to_annotation)
+                .collect::<Option<Vec<_>>>()?;
+            let ret = ir_ty_to_annotation(fun_ty.ret_ty.as_ref())?;
+
+            Some(Annotation::Fn {
+                location,
+                arguments,
+                ret: Box::new(ret),
+            })
+        }
+        IRTy::UnitTy | IRTy::UnknownTy => None,
     }
 }
 
@@ -63,9 +145,12 @@ impl From<Vec<UntypedDefinition>> for UNode {
 }
 
 impl UNode {
-    pub fn make_untyped_module(self, name: String) -> Result<UntypedModule, UError> {
+    pub fn make_untyped_module(
+        self,
+        name: String,
+        kind: ModuleKind,
+    ) -> Result<UntypedModule, UError> {
         let definitions = self.to_script_result()?;
-        let kind = ModuleKind::Validator;
 
         let module = UntypedModule {
             name,
@@ -85,6 +170,89 @@ pub enum UError {
     ExpectingDefinition,
     ExpectingScript,
     ExpectingFunDef,
+    UnresolvedImport(String),
+    ExpectingElse,
+    /// A field of a user-defined type must have a type we can express in aiken
+    FieldTypeNotSupported {
+        type_name: String,
+        field: String,
+    },
+    /// No user-defined type has exactly these fields
+    UnknownObjectLiteral(Vec<String>),
+    /// More than one user-defined type has exactly these fields
+    AmbiguousObjectLiteral(Vec<String>),
+    /// A `case` of a `switch` on a tagged union must be a string literal
+    SwitchCaseNotATag,
+    /// A `case` of a `switch` on a tagged union that is not one of its tags
+    UnknownSwitchCase {
+        type_name: String,
+        case: String,
+    },
+    /// A `switch` on a tagged union without a `default` misses some of its tags
+    NonExhaustiveSwitch {
+        type_name: String,
+        missing: Vec<String>,
+        span: SourceSpan,
+    },
+    /// A field that not all the constructors of a tagged union have, accessed where the value
+    /// has not been narrowed to the constructors that do, by a `case`
+    FieldOfUnion {
+        type_name: String,
+        field: String,
+    },
+    /// Any other `switch` needs a `default`
+    SwitchWithoutDefault,
+    /// Lists can only be traversed, e.g. with `for...of`
+    ListIndexNotSupported,
+    /// Validators are called with a fixed number of arguments,
+    /// they cannot have default or rest parameters
+    ValidatorArityNotFixed(String),
+    MissingArgument {
+        function: String,
+        param: String,
+    },
+    TooManyArguments(String),
+    /// `f(...list)` is only supported for a rest parameter of a known function
+    SpreadArgumentNotSupported(String),
+    /// Object patterns need a value of a known record type, here of type `String`
+    ObjectPatternNotSupported(String),
+    UnknownPatternField {
+        type_name: String,
+        field: String,
+    },
+    /// Array patterns need a list, or a tuple with at least as many elements and no rest
+    ArrayPatternNotSupported(String),
+    /// A `number` that the [NumericSemantics] in use cannot represent
+    NumberNotRepresentable(String),
+    /// The operands of a binary operator have different types, e.g. `1n + "a"`
+    OperandTypeMismatch {
+        op: IRBinOp,
+        left: IRTy,
+        right: IRTy,
+    },
+    /// The operator is not defined on-chain for operands of this type, e.g. `"a" < "b"`
+    OperatorNotSupported {
+        op: IRBinOp,
+        ty: IRTy,
+    },
+    /// A condition that is not a `boolean`, see [Truthiness]
+    ConditionNotBoolean(IRTy),
+    /// `Math.abs` and `Math.pow` take one and two arguments, `Math.min` and `Math.max`
+    /// at least one
+    BuiltinArity(IRBuiltin),
+    BuiltinArgumentType {
+        builtin: IRBuiltin,
+        ty: IRTy,
+    },
+    /// `bytes.slice(-2)`, whose negative indices count from the end in JS
+    NegativeIndex(IRBuiltin),
+    /// Only byte arrays have `slice` and `concat`, e.g. `list.concat(other)` is not supported
+    MethodNotSupported {
+        method: IRBuiltin,
+        ty: IRTy,
+    },
+    /// Only strings, integers and booleans can be interpolated in a template literal
+    TemplateArgumentType(IRTy),
 }
 
 impl From<UError> for Error {
@@ -94,7 +262,37 @@ impl From<UError> for Error {
 }
 
 #[derive(Default)]
-pub struct ModuleBuilderFromIR;
+pub struct ModuleBuilderFromIR {
+    /// The user-defined types of the script, by name
+    type_defs: RefCell<HashMap<String, IRTypeDef>>,
+    /// The types of the variables in scope
+    env: RefCell<TypeEnv>,
+    /// Within a `case` of a `switch` on a tagged union, the fields of the value that has been
+    /// narrowed to one constructor are bound to variables: `(value, field) -> variable`
+    narrowed: RefCell<HashMap<(String, String), String>>,
+    /// The return types of the enclosing functions, innermost last
+    ret_tys: RefCell<Vec<IRTy>>,
+    /// The names defined at the top level, which are visible everywhere
+    globals: RefCell<Vars>,
+    /// The name of the top-level definition being built, after which loop helpers are named
+    current_def: RefCell<String>,
+    /// The names used in the top-level definition being built, which its loop helpers
+    /// must not hide
+    def_names: RefCell<Vars>,
+    /// The number of loops of the definition being built
+    loops: Cell<usize>,
+    /// The functions generated for the loops of the definition being built
+    helpers: RefCell<Vec<UntypedDefinition>>,
+    /// The parameters of the functions with default or rest parameters, by name
+    arities: RefCell<HashMap<String, (Vec<IRNameTy>, IRArity)>>,
+    /// The top-level definitions of the other modules of the project, by module name
+    modules: RefCell<HashMap<String, Vec<IR>>>,
+    options: CompilerOptions,
+    /// Whether the module calls functions of `aiken/builtin`, which must then be imported
+    uses_builtin: Cell<bool>,
+    /// The functions of the prelude that the module calls, which must then be defined
+    prelude: RefCell<BTreeSet<PreludeFn>>,
+}
 
 pub type UResult = Result<UNode, UError>;
 
@@ -127,213 +325,3010 @@ impl UNodeProjector for UNode {
     }
 }
 
-impl IRVisitor<UResult> for ModuleBuilderFromIR {
-    fn new() -> Self {
-        ModuleBuilderFromIR::default()
+/// Matches the `else if` part of an `if` chain, which may or may not be enclosed in a block.
+fn as_else_if(_else: &IR) -> Option<&IRIfStmt> {
+    match _else {
+        IR::IfStmt(ifstmt) => Some(ifstmt),
+        IR::BlockStmt(IRBlockStmt { body }) => match body.as_slice() {
+            [IR::IfStmt(ifstmt)] => Some(ifstmt),
+            _ => None,
+        },
+        _ => None,
     }
+}
 
-    fn visit_script(&self, script: &IRScript) -> UResult {
-        // We allow only function definitions at the top level.
-        // Why? Well the translation of IR to a random backend, such as Aiken's UntypedDeclaration
-        // and UntypedExpr nodes, is by necessity tied to the semantics of the backend.
-        // Also, we just need to demonstrate feasibility at the moment, not completeness or even
-        // correctness.
-        let mut fundefs = Vec::with_capacity(script.body.len());
-        for ir in &script.body {
-            let fundef = match ir {
-                IR::FunDef(v) => Ok(v),
-                _ => Err(UError::ExpectingFunDef),
-            }?;
+/// The variables that the body of a loop updates, which become the state
+/// threaded through the recursive helper the loop is rewritten to. Variables defined in the
+/// body live for a single iteration, so they are not part of it.
+fn loop_state(body: &IRBlockStmt) -> Vars {
+    block_assigned_vars(&body.body)
+}
+
+/// Whether `ir` is made of empty blocks only
+fn does_nothing(ir: &IR) -> bool {
+    match ir {
+        IR::BlockStmt(IRBlockStmt { body }) => body.iter().all(does_nothing),
+        IR::IfStmt(ifstmt) => does_nothing(&ifstmt._then) && does_nothing(&ifstmt._else),
+        _ => false,
+    }
+}
+
+/// The variables `vars` as a single value, e.g. `(a, b)`
+fn vars_expr(vars: &[String]) -> UntypedExpr {
+    let location = no_span();
+    let var = |name: &str| UntypedExpr::Var {
+        location,
+        name: name.to_string(),
+    };
+
+    match vars {
+        // Nothing to return, the loop is only there for its checks
+        [] => var("True"),
+        [name] => var(name),
+        vars => UntypedExpr::Tuple {
+            location,
+            elems: vars.iter().map(|name| var(name)).collect(),
+        },
+    }
+}
+
+/// The pattern that binds the variables `vars` from the value of [vars_expr]
+fn vars_pattern(vars: &[String]) -> UntypedPattern {
+    let location = no_span();
+    let var = |name: &String| UntypedPattern::Var {
+        location,
+        name: name.clone(),
+    };
+
+    match vars {
+        [] => UntypedPattern::Discard {
+            name: "_".to_string(),
+            location,
+        },
+        [name] => var(name),
+        vars => UntypedPattern::Tuple {
+            location,
+            elems: vars.iter().map(var).collect(),
+        },
+    }
+}
+
+/// The name bound to the value of an option in the `Some` branch of a `when`,
+/// see [ModuleBuilderFromIR::make_option_when]
+const OPTION_VALUE: &str = "option_value";
+
+/// The only value of aiken's unit type, which later versions of aiken call `Void`
+const UNIT: &str = "Nil";
+
+/// `Some(value)`
+fn some(value: UntypedExpr) -> UntypedExpr {
+    let location = no_span();
+
+    UntypedExpr::Call {
+        arguments: vec![CallArg {
+            label: None,
+            location,
+            value,
+        }],
+        fun: Box::new(UntypedExpr::Var {
+            location,
+            name: "Some".to_string(),
+        }),
+        location,
+    }
+}
+
+/// `None`, for `undefined`
+fn none() -> UntypedExpr {
+    UntypedExpr::Var {
+        location: no_span(),
+        name: "None".to_string(),
+    }
+}
+
+/// Whether `expr` is a negative number, as far as we can tell, e.g. `-1` or `-n`
+fn is_negative(expr: &IRExpr) -> bool {
+    match expr {
+        IRExpr::Unary(unary) => matches!(unary.op, IRUnOp::Minus),
+        IRExpr::Literal(IRLiteral::Float64(value)) => *value < 0.0,
+        IRExpr::Literal(IRLiteral::BigInt(value)) => value.sign() == Sign::Minus,
+        _ => false,
+    }
+}
 
-            let fundef = self.visit_fundef(fundef)?.to_def_result()?;
-            fundefs.push(fundef);
+/// Whether `pattern` may not match, which is the case of list patterns,
+/// e.g. `[first, ..rest]` does not match the empty list
+fn is_refutable(pattern: &UntypedPattern) -> bool {
+    match pattern {
+        UntypedPattern::List { .. } => true,
+        UntypedPattern::Tuple { elems, .. } => elems.iter().any(is_refutable),
+        UntypedPattern::Constructor { arguments, .. } => {
+            arguments.iter().any(|arg| is_refutable(&arg.value))
         }
+        _ => false,
+    }
+}
+
+/// The parameters as the function receives them: the ones with a default value are options,
+/// see [ModuleBuilderFromIR::make_call_args]
+fn received_params(params: &[IRNameTy], arity: &IRArity) -> Vec<IRNameTy> {
+    params
+        .iter()
+        .zip(&arity.defaults)
+        .map(|(param, default)| match default {
+            Some(_) => IRNameTy {
+                name: param.name.clone(),
+                ty: param.ty.clone().optional(),
+            },
+            None => param.clone(),
+        })
+        .collect()
+}
+
+/// Ends a branch with the value it yields, if any, see [crate::ir_ssa].
+fn with_yield(body: UntypedExpr, yielded: Option<&UntypedExpr>) -> UntypedExpr {
+    let yielded = match yielded {
+        None => return body,
+        Some(yielded) => yielded.clone(),
+    };
+
+    let mut expressions = match body {
+        UntypedExpr::Sequence { expressions, .. } => expressions,
+        body => vec![body],
+    };
+    expressions.push(yielded);
+
+    UntypedExpr::Sequence {
+        location: no_span(),
+        expressions,
+    }
+}
 
-        Ok(fundefs.into())
+impl ModuleBuilderFromIR {
+    pub fn with_options(options: CompilerOptions) -> Self {
+        ModuleBuilderFromIR {
+            options,
+            ..Default::default()
+        }
     }
 
-    fn visit_fundef(&self, fundef: &IRFunDef) -> UResult {
-        let mut arguments = Vec::with_capacity(fundef.params.len());
-        for param in &fundef.params {
-            let location = no_span();
-            let name = param.name.as_ref().ident.clone();
-            let arg_name = ArgName::Named { name, location };
+    /// Makes the definitions of `module` known, so that the types and functions it exports
+    /// can be used once imported.
+    pub fn bind_module(&self, name: &str, module: &IR) {
+        if let IR::Script(script) = module {
+            self.modules
+                .borrow_mut()
+                .insert(name.to_string(), script.body.clone());
+        }
+    }
 
-            let location = no_span();
-            let arg = Arg {
-                arg_name,
-                location: no_span(),
-                annotation: None,
-                tipo: (),
+    /// The top-level definition named `name` in the imported `module`
+    fn imported_def(&self, module: &str, name: &str) -> Option<IR> {
+        let modules = self.modules.borrow();
+        modules.get(module)?.iter().find_map(|ir| {
+            let defined = match ir {
+                IR::TypeDef(IRTypeDef { name, .. })
+                | IR::FunDef(IRFunDef { name, .. })
+                | IR::VarDef(IRVarDef { name, .. }) => name,
+                _ => return None,
             };
 
-            arguments.push(arg);
-        }
-        let body = fundef.body.as_ref();
-        let body = self.visit_blockstmt(body)?.to_expr_result()?;
-        let doc = None;
-        let location = no_span();
-        let name = fundef.name.as_ref().ident.clone();
+            (defined.ident == name).then(|| ir.clone())
+        })
+    }
 
-        let result = UntypedDefinition::Fn(Function {
-            arguments,
-            body,
-            doc,
-            location,
-            name,
-            public: true,
-            return_annotation: None,
-            return_type: (),
-            end_position: 0,
-        });
+    /// Binds the types, functions and arities of what `import` brings into scope, under
+    /// their local names.
+    fn bind_import(&self, import: &IRImport) {
+        for specifier in &import.specifiers {
+            let local = &specifier.alias.as_ref().unwrap_or(&specifier.name).ident;
+            self.globals.borrow_mut().insert(local.clone());
 
-        Ok(result.into())
+            let def = import
+                .module
+                .as_ref()
+                .and_then(|module| self.imported_def(module, &specifier.name.ident));
+            match def {
+                Some(IR::TypeDef(mut typedef)) => {
+                    // The constructor of a record is imported along with the type, alias included
+                    for constructor in &mut typedef.constructors {
+                        if constructor.name.ident == typedef.name.ident {
+                            constructor.name.ident = local.clone();
+                        }
+                    }
+                    typedef.name.ident = local.clone();
+                    self.type_defs.borrow_mut().insert(local.clone(), typedef);
+                }
+                Some(IR::FunDef(fundef)) => {
+                    let ty = IRTy::FunTy(Box::new(fundef.fun_ty.clone()));
+                    self.env.borrow_mut().bind(local.clone(), ty);
+                    self.bind_arity(local, &fundef.params, &fundef.arity);
+                }
+                Some(IR::VarDef(vardef)) => {
+                    if let IRExpr::Lambda(lambda) = vardef.value.as_ref() {
+                        let ty = IRTy::FunTy(Box::new(lambda.fun_ty.clone()));
+                        self.env.borrow_mut().bind(local.clone(), ty);
+                        self.bind_arity(local, &lambda.params, &lambda.arity);
+                    }
+                }
+                _ => {}
+            }
+        }
     }
 
-    fn visit_vardef(&self, vardef: &IRVarDef) -> UResult {
-        let location = no_span();
-        let name = vardef.name.as_ref().ident.clone();
+    fn make_if_branch(
+        &self,
+        ifstmt: &IRIfStmt,
+        yielded: Option<&UntypedExpr>,
+    ) -> Result<IfBranch<UntypedExpr>, UError> {
+        let condition = self.visit_condition(ifstmt._if.as_ref())?;
+        let body = self.visit_ir(ifstmt._then.as_ref())?.to_expr_result()?;
+        let body = with_yield(body, yielded);
 
-        let value = self.visit_expr(vardef.value.as_ref())?.to_expr_result()?;
-        let value = Box::new(value);
+        Ok(IfBranch {
+            condition,
+            body,
+            location: no_span(),
+        })
+    }
 
-        let pattern = UntypedPattern::Var { location, name };
+    /// Finds the constructor an object literal stands for, which is the one whose fields
+    /// are exactly the properties of the literal (TS types are structural after all),
+    /// except for optional fields that may be left out.
+    /// For tagged unions, the discriminant property must also have the constructor's tag.
+    /// When the literal is expected to be of a known type, only the constructors of that
+    /// type are considered, as several types may well have the same fields.
+    fn resolve_constructor(
+        &self,
+        object: &IRObjectLit,
+        expected_ty: &IRTy,
+    ) -> Result<(IRTypeDef, IRConstructor), UError> {
+        let mut props: Vec<&String> = object.props.iter().map(|p| &p.name.ident).collect();
+        props.sort();
 
-        let result = UntypedExpr::Assignment {
-            location,
-            value,
-            pattern,
-            kind: AssignmentKind::Let,
-            annotation: None,
+        let type_defs = self.type_defs.borrow();
+        let typedefs: Vec<&IRTypeDef> = match expected_ty {
+            IRTy::NamedTy(name, _) if type_defs.contains_key(name) => vec![&type_defs[name]],
+            _ => type_defs.values().collect(),
         };
+        let expected = &props;
+        let mut candidates = typedefs.into_iter().flat_map(|typedef| {
+            typedef
+                .constructors
+                .iter()
+                .filter(move |constructor| {
+                    let mut fields: Vec<&String> = constructor
+                        .fields
+                        .iter()
+                        .map(|f| &f.name.ident)
+                        .filter(|name| {
+                            expected.contains(name) || !constructor.is_optional_field(name)
+                        })
+                        .collect();
+                    fields.extend(typedef.discriminant.as_ref());
+                    fields.sort();
 
-        Ok(result.into())
+                    &fields == expected && constructor.tag.as_ref() == self.tag_of(typedef, object)
+                })
+                .map(move |constructor| (typedef, constructor))
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some((typedef, constructor)), None) => Ok((typedef.clone(), constructor.clone())),
+            (None, _) => Err(UError::UnknownObjectLiteral(
+                props.iter().map(|p| p.to_string()).collect(),
+            )),
+            (Some(_), Some(_)) => Err(UError::AmbiguousObjectLiteral(
+                props.iter().map(|p| p.to_string()).collect(),
+            )),
+        }
     }
 
-    fn visit_retstmt(&self, retstmt: &IRReturnStmt) -> UResult {
-        // TODO the semantics are not quite right here
-        //      but this can be a good enough approximation for a demo.
-        match &retstmt.expr {
-            None => Err(UError::ExpectingExpr),
-            Some(v) => {
-                let expr = self.visit_expr(v)?.to_expr_result()?;
-                Ok(expr.into())
+    /// The value of the discriminant property of `object`, if `typedef` is a tagged union.
+    fn tag_of<'a>(&self, typedef: &IRTypeDef, object: &'a IRObjectLit) -> Option<&'a String> {
+        let discriminant = typedef.discriminant.as_ref()?;
+        object
+            .props
+            .iter()
+            .find(|prop| &prop.name.ident == discriminant)
+            .and_then(|prop| match &prop.value {
+                IRExpr::Literal(IRLiteral::String(tag)) => Some(tag),
+                _ => None,
+            })
+    }
+
+    /// Like [IRVisitor::visit_expr], but array literals become tuples where tuples are expected,
+    /// values become `Some(value)` where options are expected, and object literals are built
+    /// with the constructors of the expected type.
+    fn visit_expr_expecting(&self, expr: &IRExpr, expected: &IRTy) -> Result<UntypedExpr, UError> {
+        let location = no_span();
+
+        match (expr, expected) {
+            (IRExpr::Cond(cond), _) => self.make_cond(cond, expected),
+            (IRExpr::Object(object), IRTy::NamedTy(..)) => self.make_object(object, expected),
+            (_, IRTy::OptionTy(ty)) => match self.type_of(expr) {
+                IRTy::OptionTy(_) | IRTy::UnknownTy => self.visit_expr(expr)?.to_expr_result(),
+                _ => Ok(some(self.visit_expr_expecting(expr, ty)?)),
+            },
+            (IRExpr::Array(array), IRTy::TupleTy(elem_tys))
+                if array.elements.len() == elem_tys.len() =>
+            {
+                let mut elems = Vec::with_capacity(elem_tys.len());
+                for (element, elem_ty) in array.elements.iter().zip(elem_tys) {
+                    elems.push(self.visit_expr_expecting(element, elem_ty)?);
+                }
+
+                Ok(UntypedExpr::Tuple { location, elems })
+            }
+            (IRExpr::Array(array), IRTy::ListTy(elem_ty)) => {
+                let mut elements = Vec::with_capacity(array.elements.len());
+                for element in &array.elements {
+                    elements.push(self.visit_expr_expecting(element, elem_ty)?);
+                }
+
+                Ok(UntypedExpr::List {
+                    location,
+                    elements,
+                    tail: None,
+                })
             }
+            _ => self.visit_expr(expr)?.to_expr_result(),
         }
     }
 
-    fn visit_blockstmt(&self, blockstmt: &IRBlockStmt) -> UResult {
+    /// An object literal, as a call to the constructor it resolves to
+    fn make_object(&self, object: &IRObjectLit, expected: &IRTy) -> Result<UntypedExpr, UError> {
         let location = no_span();
-        let body = &blockstmt.body;
-        let mut expressions = Vec::with_capacity(body.len());
+        let (typedef, constructor) = self.resolve_constructor(object, expected)?;
+        let type_args = match expected {
+            IRTy::NamedTy(_, type_args) => type_args.as_slice(),
+            _ => &[],
+        };
+
+        let fun = UntypedExpr::Var {
+            location,
+            name: constructor.name.ident.clone(),
+        };
 
-        for ir in body {
-            let expr = self.visit_ir(ir)?.to_expr_result()?;
-            expressions.push(expr);
+        // Constructors without fields are constants
+        if constructor.fields.is_empty() {
+            return Ok(fun);
         }
 
-        let result = UntypedExpr::Sequence {
-            location: no_span(),
-            expressions,
-        };
+        let mut arguments = Vec::with_capacity(object.props.len());
+        for prop in &object.props {
+            // The tag is implied by the constructor
+            if typedef.discriminant.as_ref() == Some(&prop.name.ident) {
+                continue;
+            }
 
-        Ok(result.into())
+            let field_ty = constructor
+                .fields
+                .iter()
+                .find(|field| field.name.ident == prop.name.ident)
+                .map(|field| field.ty.instantiate(&typedef.type_params, type_args))
+                .unwrap_or(IRTy::UnknownTy);
+            let value = self.visit_expr_expecting(&prop.value, &field_ty)?;
+            arguments.push(CallArg {
+                label: Some(prop.name.ident.clone()),
+                location,
+                value,
+            });
+        }
+
+        // Optional fields left out are `undefined`
+        for field in &constructor.fields {
+            if !object
+                .props
+                .iter()
+                .any(|prop| prop.name.ident == field.name.ident)
+            {
+                arguments.push(CallArg {
+                    label: Some(field.name.ident.clone()),
+                    location,
+                    value: none(),
+                });
+            }
+        }
+
+        Ok(UntypedExpr::Call {
+            arguments,
+            fun: Box::new(fun),
+            location,
+        })
     }
 
-    fn visit_ifstmt(&self, ifstmt: &IRIfStmt) -> UResult {
+    /// `test ? then : otherwise`, whose branches are values of type `expected`
+    fn make_cond(&self, cond: &IRCondExpr, expected: &IRTy) -> Result<UntypedExpr, UError> {
         let location = no_span();
 
-        let condition = self.visit_expr(ifstmt._if.as_ref())?.to_expr_result()?;
-        let body = self.visit_ir(ifstmt._else.as_ref())?.to_expr_result()?;
+        let condition = self.visit_condition(cond._if.as_ref())?;
+        let body = self.visit_expr_expecting(cond._then.as_ref(), expected)?;
+        let final_else = self.visit_expr_expecting(cond._else.as_ref(), expected)?;
 
         let if_branch = IfBranch {
             condition,
             body,
             location,
         };
-        let branches = vec1![if_branch];
 
-        let final_else = self.visit_ir(ifstmt._else.as_ref())?.to_expr_result()?;
-        let final_else = Box::new(final_else);
+        Ok(UntypedExpr::If {
+            location,
+            branches: vec1![if_branch],
+            final_else: Box::new(final_else),
+        })
+    }
 
-        let result = UntypedExpr::If {
-            location: no_span(),
-            branches,
-            final_else,
+    /// `when option is { Some(option_value) -> some  None -> none }`
+    fn make_option_when(
+        &self,
+        option: UntypedExpr,
+        some: UntypedExpr,
+        none: UntypedExpr,
+    ) -> UntypedExpr {
+        let location = no_span();
+        let constructor = |name: &str, arguments| UntypedPattern::Constructor {
+            is_record: false,
+            location,
+            name: name.to_string(),
+            arguments,
+            module: None,
+            constructor: (),
+            with_spread: false,
+            tipo: (),
+        };
+        let value = CallArg {
+            label: None,
+            location,
+            value: UntypedPattern::Var {
+                location,
+                name: OPTION_VALUE.to_string(),
+            },
         };
 
-        Ok(result.into())
+        let clause = |pattern, then| Clause {
+            location,
+            pattern: vec![pattern],
+            alternative_patterns: vec![],
+            guard: None,
+            then,
+        };
+
+        UntypedExpr::When {
+            location,
+            subjects: vec![option],
+            clauses: vec![
+                clause(constructor("Some", vec![value]), some),
+                clause(constructor("None", vec![]), none),
+            ],
+        }
     }
 
-    fn visit_exprstmt(&self, exprstmt: &IRExprStmt) -> UResult {
-        let expr = self.visit_expr(&exprstmt.expr)?.to_expr_result()?;
+    /// `x ?? d` is `when x is { Some(option_value) -> option_value  None -> d }`,
+    /// or `Some(option_value)` in the first branch if `d` is optional too.
+    fn make_nullish_coalescing(&self, binary_op: &IRBinaryExpr) -> Result<UntypedExpr, UError> {
+        let option = self.visit_expr(&binary_op.left)?.to_expr_result()?;
 
-        let result = UntypedExpr::Sequence {
+        let ty = match self.type_of(&binary_op.left) {
+            IRTy::OptionTy(ty) => *ty,
+            IRTy::UnknownTy => IRTy::UnknownTy,
+            // Never `undefined`, so the default is never used
+            _ => return Ok(option),
+        };
+        let value = UntypedExpr::Var {
             location: no_span(),
-            expressions: vec![expr],
+            name: OPTION_VALUE.to_string(),
+        };
+        let (value, default) = match self.type_of(&binary_op.right) {
+            IRTy::OptionTy(_) => (
+                some(value),
+                self.visit_expr(&binary_op.right)?.to_expr_result()?,
+            ),
+            _ => (value, self.visit_expr_expecting(&binary_op.right, &ty)?),
         };
 
-        Ok(result.into())
+        Ok(self.make_option_when(option, value, default))
     }
 
-    fn visit_literal(&self, literal: &IRLiteral) -> UResult {
-        let location = no_span();
+    /// Runs `f` in a new scope of variables.
+    fn in_scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.env.borrow_mut().push_scope();
+        let result = f();
+        self.env.borrow_mut().pop_scope();
 
-        let result = match literal {
-            IRLiteral::Boolean(v) => UntypedExpr::Var {
-                location,
-                name: (if *v { "True" } else { "False" }).to_string(),
-            },
-            IRLiteral::Float64(v) => UntypedExpr::Int {
-                location,
-                value: v.to_string(), // TODO IRLiteral::Float64 to UntypedExpr::Int ??
-            },
-            IRLiteral::String(v) => UntypedExpr::String {
-                location,
-                value: v.clone(),
-            },
-            IRLiteral::BigInt(v) => UntypedExpr::Int {
-                location,
-                value: v.to_string(),
-            },
-        };
+        result
+    }
 
-        Ok(result.into())
+    /// Runs `f` in the scope of the body of a function.
+    fn in_function<R>(&self, params: &[IRNameTy], fun_ty: &IRFunTy, f: impl FnOnce() -> R) -> R {
+        self.ret_tys
+            .borrow_mut()
+            .push(fun_ty.ret_ty.as_ref().clone());
+        let result = self.in_scope(|| {
+            self.bind_params(params);
+            f()
+        });
+        self.ret_tys.borrow_mut().pop();
+
+        result
     }
 
-    fn visit_ident(&self, ident: &IRIdent) -> UResult {
-        let result = UntypedExpr::Var {
-            location: no_span(),
-            name: ident.ident.clone(),
+    fn bind_arity(&self, name: &str, params: &[IRNameTy], arity: &IRArity) {
+        if !arity.is_fixed() {
+            let signature = (params.to_vec(), arity.clone());
+            self.arities
+                .borrow_mut()
+                .insert(name.to_string(), signature);
+        }
+    }
+
+    /// The arguments of a call, one per parameter of the function: the arguments for
+    /// parameters with a default value are options, `None` when missing, since the defaults
+    /// are computed by the function itself, see [ModuleBuilderFromIR::make_defaults].
+    /// The arguments for a rest parameter are collected in a list.
+    fn make_call_args(&self, apply: &IRApply) -> Result<Vec<UntypedExpr>, UError> {
+        let function = apply.name.ident.clone();
+        let signature = self.arities.borrow().get(&function).cloned();
+
+        let (params, arity) = match signature {
+            Some(signature) => signature,
+            // One argument per parameter
+            None => {
+                if apply.spread.is_some() {
+                    return Err(UError::SpreadArgumentNotSupported(function));
+                }
+
+                let param_tys = match self.env.borrow().lookup(&function) {
+                    IRTy::FunTy(fun_ty) => fun_ty.param_tys,
+                    _ => vec![],
+                };
+
+                let mut values = Vec::with_capacity(apply.args.len());
+                for (i, arg) in apply.args.iter().enumerate() {
+                    let param_ty = param_tys.get(i).cloned().unwrap_or(IRTy::UnknownTy);
+                    values.push(self.visit_expr_expecting(arg, &param_ty)?);
+                }
+
+                return Ok(values);
+            }
         };
 
-        Ok(result.into())
+        let fixed = if arity.rest {
+            params.len() - 1
+        } else {
+            params.len()
+        };
+
+        let mut args = apply.args.iter();
+        let mut values = Vec::with_capacity(params.len());
+        for (param, default) in params.iter().zip(&arity.defaults).take(fixed) {
+            let value = match (args.next(), default) {
+                (Some(arg), None) => self.visit_expr_expecting(arg, &param.ty)?,
+                // As in Typescript, an `undefined` argument gets the default value too
+                (Some(arg), Some(_)) => {
+                    self.visit_expr_expecting(arg, &param.ty.clone().optional())?
+                }
+                (None, Some(_)) => none(),
+                (None, None) => {
+                    return Err(UError::MissingArgument {
+                        function,
+                        param: param.name.ident.clone(),
+                    })
+                }
+            };
+            values.push(value);
+        }
+
+        let extra: Vec<&IRExpr> = args.collect();
+        match (arity.rest, &apply.spread) {
+            (true, None) => {
+                let rest_ty = &params[fixed].ty;
+                let elem_ty = match rest_ty {
+                    IRTy::ListTy(elem_ty) => elem_ty.as_ref().clone(),
+                    _ => IRTy::UnknownTy,
+                };
+
+                let mut elements = Vec::with_capacity(extra.len());
+                for arg in extra {
+                    elements.push(self.visit_expr_expecting(arg, &elem_ty)?);
+                }
+
+                values.push(UntypedExpr::List {
+                    location: no_span(),
+                    elements,
+                    tail: None,
+                });
+            }
+            (true, Some(spread)) if extra.is_empty() => {
+                values.push(self.visit_expr(spread)?.to_expr_result()?);
+            }
+            (false, None) if extra.is_empty() => {}
+            (false, None) => return Err(UError::TooManyArguments(function)),
+            (_, Some(_)) => return Err(UError::SpreadArgumentNotSupported(function)),
+        }
+
+        Ok(values)
     }
 
-    fn visit_binary_op(&self, binary_op: &IRBinaryExpr) -> UResult {
-        let location = no_span();
-        let name = ir_bin_op_to_bin_op(binary_op.op.clone());
+    fn bind_params(&self, params: &[IRNameTy]) {
+        let mut env = self.env.borrow_mut();
+        for param in params {
+            env.bind(param.name.ident.clone(), param.ty.clone());
+        }
+    }
 
-        let left = self.visit_expr(binary_op.left.as_ref())?.to_expr_result()?;
-        let right = self
-            .visit_expr(binary_op.right.as_ref())?
-            .to_expr_result()?;
+    /// A best-effort computation of the type of an expression, from what we know about
+    /// the types of variables, functions and user-defined types.
+    fn type_of(&self, expr: &IRExpr) -> IRTy {
+        match expr {
+            IRExpr::Literal(literal) => match literal {
+                IRLiteral::Boolean(_) => IRTy::BooleanTy,
+                IRLiteral::Float64(_) => IRTy::Float64Ty,
+                IRLiteral::BigInt(_) => IRTy::BigIntTy,
+                IRLiteral::String(_) => IRTy::StringTy,
+                IRLiteral::ByteArray(_) => IRTy::ByteArrayTy,
+                IRLiteral::Undefined => IRTy::OptionTy(Box::new(IRTy::UnknownTy)),
+            },
+            IRExpr::Identifier(ident) => self.env.borrow().lookup(&ident.ident),
+            IRExpr::Unary(unary) => match unary.op {
+                IRUnOp::Not => IRTy::BooleanTy,
+                IRUnOp::Minus | IRUnOp::Plus => self.type_of(&unary.expr),
+            },
+            IRExpr::Binary(binary) => match binary.op {
+                IRBinOp::Add
+                | IRBinOp::Sub
+                | IRBinOp::Mul
+                | IRBinOp::Div
+                | IRBinOp::Mod
+                | IRBinOp::Exp => self.operand_ty(binary).unwrap_or(IRTy::UnknownTy),
+                IRBinOp::NullishCoalescing => {
+                    match (self.type_of(&binary.left), self.type_of(&binary.right)) {
+                        // `x ?? undefined`
+                        (left, IRTy::OptionTy(ty)) if *ty == IRTy::UnknownTy => left,
+                        (IRTy::OptionTy(ty), IRTy::UnknownTy) => *ty,
+                        (IRTy::OptionTy(_) | IRTy::UnknownTy, right) => right,
+                        (left, _) => left,
+                    }
+                }
+                _ => IRTy::BooleanTy,
+            },
+            IRExpr::Cond(cond) => self.type_of(&cond._then),
+            IRExpr::Object(object) => match self.resolve_constructor(object, &IRTy::UnknownTy) {
+                Ok((typedef, constructor)) => {
+                    let field_tys: Vec<IRTy> = constructor
+                        .fields
+                        .iter()
+                        .map(|field| {
+                            object
+                                .props
+                                .iter()
+                                .find(|prop| prop.name.ident == field.name.ident)
+                                .map_or(IRTy::UnknownTy, |prop| self.type_of(&prop.value))
+                        })
+                        .collect();
 
-        let left = Box::new(left);
-        let right = Box::new(right);
+                    typedef.constructed_ty(&constructor, &field_tys)
+                }
+                Err(_) => IRTy::UnknownTy,
+            },
+            IRExpr::Member(member) => {
+                self.member_ty(self.type_of(&member.object), &member.prop.ident)
+            }
+            IRExpr::OptMember(member) => match self.type_of(&member.object) {
+                IRTy::OptionTy(ty) => self.member_ty(*ty, &member.prop.ident).optional(),
+                _ => IRTy::UnknownTy,
+            },
+            IRExpr::Array(array) => {
+                let elem_ty = match array.elements.first() {
+                    Some(element) => self.type_of(element),
+                    None => IRTy::UnknownTy,
+                };
 
-        let result = UntypedExpr::BinOp {
-            location,
-            name,
-            left,
-            right,
+                IRTy::ListTy(Box::new(elem_ty))
+            }
+            IRExpr::Index(index) => match self.type_of(&index.object) {
+                IRTy::TupleTy(elem_tys) => elem_tys
+                    .get(index.index)
+                    .cloned()
+                    .unwrap_or(IRTy::UnknownTy),
+                IRTy::ListTy(elem_ty) => *elem_ty,
+                _ => IRTy::UnknownTy,
+            },
+            IRExpr::Apply(apply) => match self.env.borrow().lookup(&apply.name.ident) {
+                IRTy::FunTy(fun_ty) => {
+                    let arg_tys: Vec<IRTy> =
+                        apply.args.iter().map(|arg| self.type_of(arg)).collect();
+                    fun_ty.instantiate_ret_ty(&arg_tys)
+                }
+                _ => IRTy::UnknownTy,
+            },
+            // The functions of `Math` return numbers of the type of their arguments,
+            // and those on byte arrays byte arrays
+            IRExpr::Builtin(builtin_call) => match builtin_call.args.first() {
+                Some(arg) => self.type_of(arg),
+                None => IRTy::UnknownTy,
+            },
+            IRExpr::Template(_) => IRTy::StringTy,
+            IRExpr::Lambda(lambda) => IRTy::FunTy(Box::new(lambda.fun_ty.clone())),
+        }
+    }
+
+    /// The type of the property `prop` of a value of type `ty`
+    fn member_ty(&self, ty: IRTy, prop: &str) -> IRTy {
+        match ty {
+            // Lengths are integers on-chain, whatever the [NumericSemantics]
+            IRTy::ByteArrayTy if prop == "length" => IRTy::BigIntTy,
+            IRTy::NamedTy(..) => self
+                .typedef_of(&ty)
+                .and_then(|typedef| {
+                    typedef
+                        .constructors
+                        .into_iter()
+                        .flat_map(|constructor| constructor.fields)
+                        .find(|field| field.name.ident == prop)
+                })
+                .map(|field| field.ty)
+                .unwrap_or(IRTy::UnknownTy),
+            _ => IRTy::UnknownTy,
+        }
+    }
+
+    /// The definition of the user-defined type `ty`, applied to its type arguments
+    fn typedef_of(&self, ty: &IRTy) -> Option<IRTypeDef> {
+        match ty {
+            IRTy::NamedTy(name, type_args) => self
+                .type_defs
+                .borrow()
+                .get(name)
+                .map(|typedef| typedef.instantiate(type_args)),
+            _ => None,
+        }
+    }
+
+    /// If `discriminant` is `value.kind`, where `value` is a variable of a tagged union type
+    /// whose discriminant is `kind`, returns `value` and the tagged union.
+    fn as_union_discriminant(&self, discriminant: &IRExpr) -> Option<(String, IRTypeDef)> {
+        let member = match discriminant {
+            IRExpr::Member(member) => member,
+            _ => return None,
+        };
+        let value = match member.object.as_ref() {
+            IRExpr::Identifier(ident) => ident.ident.clone(),
+            _ => return None,
         };
+        let typedef = self.typedef_of(&self.type_of(&member.object))?;
 
-        Ok(result.into())
+        if typedef.discriminant.as_ref() == Some(&member.prop.ident) {
+            Some((value, typedef))
+        } else {
+            None
+        }
     }
 
-    fn visit_apply(&self, apply: &IRApply) -> UResult {
-        // TODO Implement
-        todo!()
+    /// The common type of the operands of `binary_op`, where an unknown type is assumed to be
+    /// the type of the other operand. A `number` literal next to a `bigint` is a `bigint`,
+    /// as in `i = i + 1` for `i++`.
+    fn operand_ty(&self, binary_op: &IRBinaryExpr) -> Result<IRTy, UError> {
+        let is_number_literal =
+            |expr: &IRExpr| matches!(expr, IRExpr::Literal(IRLiteral::Float64(_)));
+
+        let left = self.type_of(&binary_op.left);
+        let right = self.type_of(&binary_op.right);
+
+        match (left, right) {
+            (IRTy::UnknownTy, ty) | (ty, IRTy::UnknownTy) => Ok(ty),
+            // `x === undefined`
+            (IRTy::OptionTy(ty), IRTy::OptionTy(other)) if *other == IRTy::UnknownTy => {
+                Ok(IRTy::OptionTy(ty))
+            }
+            (IRTy::OptionTy(other), IRTy::OptionTy(ty)) if *other == IRTy::UnknownTy => {
+                Ok(IRTy::OptionTy(ty))
+            }
+            (IRTy::BigIntTy, IRTy::Float64Ty) if is_number_literal(binary_op.right.as_ref()) => {
+                Ok(IRTy::BigIntTy)
+            }
+            (IRTy::Float64Ty, IRTy::BigIntTy) if is_number_literal(binary_op.left.as_ref()) => {
+                Ok(IRTy::BigIntTy)
+            }
+            (left, right) if left == right => Ok(left),
+            (left, right) => Err(UError::OperandTypeMismatch {
+                op: binary_op.op.clone(),
+                left,
+                right,
+            }),
+        }
+    }
+
+    /// A condition, which must be a `boolean` unless [Truthiness::Coerce] is in use.
+    /// The operands of `&&` and `||` are conditions too, since the result is only used
+    /// for its truthiness.
+    fn visit_condition(&self, expr: &IRExpr) -> Result<UntypedExpr, UError> {
+        let location = no_span();
+
+        if let IRExpr::Binary(binary) = expr {
+            let name = match binary.op {
+                IRBinOp::LogicalAnd => Some(BinOp::And),
+                IRBinOp::LogicalOr => Some(BinOp::Or),
+                _ => None,
+            };
+
+            if let Some(name) = name {
+                let result = UntypedExpr::BinOp {
+                    location,
+                    name,
+                    left: Box::new(self.visit_condition(&binary.left)?),
+                    right: Box::new(self.visit_condition(&binary.right)?),
+                };
+
+                return Ok(result);
+            }
+        }
+
+        let ty = self.type_of(expr);
+        if matches!(ty, IRTy::BooleanTy | IRTy::UnknownTy) {
+            return self.visit_expr(expr)?.to_expr_result();
+        }
+        if self.options.truthiness == Truthiness::BooleanOnly {
+            return Err(UError::ConditionNotBoolean(ty));
+        }
+
+        // `undefined` is falsy, and so is the value of `Some(value)` if it is falsy itself.
+        // Objects are always truthy, so `if (datum)` only tells whether there is one.
+        if let IRTy::OptionTy(value_ty) = &ty {
+            let option = self.visit_expr(expr)?.to_expr_result()?;
+            let value = IRExpr::Identifier(IRIdent {
+                ident: OPTION_VALUE.to_string(),
+            });
+            let some = match value_ty.as_ref() {
+                IRTy::NamedTy(..) | IRTy::TupleTy(_) | IRTy::ByteArrayTy | IRTy::FunTy(_) => {
+                    UntypedExpr::Var {
+                        location,
+                        name: "True".to_string(),
+                    }
+                }
+                value_ty => self.in_scope(|| {
+                    self.env
+                        .borrow_mut()
+                        .bind(OPTION_VALUE.to_string(), value_ty.clone());
+                    self.visit_condition(&value)
+                })?,
+            };
+            let none = UntypedExpr::Var {
+                location,
+                name: "False".to_string(),
+            };
+
+            return Ok(self.make_option_when(option, some, none));
+        }
+
+        // The falsy value of the type, which the condition must differ from
+        let falsy = match &ty {
+            IRTy::Float64Ty | IRTy::BigIntTy => UntypedExpr::Int {
+                location,
+                value: "0".to_string(),
+            },
+            IRTy::StringTy => UntypedExpr::String {
+                location,
+                value: "".to_string(),
+            },
+            IRTy::ListTy(_) => UntypedExpr::List {
+                location,
+                elements: vec![],
+                tail: None,
+            },
+            _ => return Err(UError::ConditionNotBoolean(ty)),
+        };
+
+        let result = UntypedExpr::BinOp {
+            location,
+            name: BinOp::NotEq,
+            left: Box::new(self.visit_expr(expr)?.to_expr_result()?),
+            right: Box::new(falsy),
+        };
+
+        Ok(result)
+    }
+
+    /// An expression that is an integer whatever the [NumericSemantics], such as an index,
+    /// so that a `number` literal is never scaled
+    fn visit_integer(&self, expr: &IRExpr) -> Result<UntypedExpr, UError> {
+        match expr {
+            IRExpr::Literal(IRLiteral::Float64(v)) => Ok(UntypedExpr::Int {
+                location: no_span(),
+                value: NumericSemantics::SafeInteger
+                    .number_literal(*v)
+                    .ok_or_else(|| UError::NumberNotRepresentable(v.to_string()))?
+                    .to_string(),
+            }),
+            expr => self.visit_expr(expr)?.to_expr_result(),
+        }
+    }
+
+    /// `name(arguments)`, a function of our [prelude](crate::aiken_prelude)
+    fn prelude_call(&self, prelude_fn: PreludeFn, arguments: Vec<UntypedExpr>) -> UntypedExpr {
+        self.prelude.borrow_mut().insert(prelude_fn);
+        if prelude_fn.uses_builtin() {
+            self.uses_builtin.set(true);
+        }
+
+        prelude_fn.call(arguments)
+    }
+
+    /// `builtin.name(arguments)`, a function of aiken's `aiken/builtin` module
+    fn builtin_call(&self, name: &str, arguments: Vec<UntypedExpr>) -> UntypedExpr {
+        self.uses_builtin.set(true);
+        aiken_prelude::builtin(name, arguments)
+    }
+
+    /// The aiken pattern for a destructuring `pattern` of a value of type `ty`, binding
+    /// the types of its variables in the environment.
+    ///
+    /// Object patterns become record patterns, `Datum { owner, deadline: d, .. }`, array
+    /// patterns become tuple patterns for tuples, and list patterns otherwise.
+    fn make_pattern(&self, pattern: &IRPattern, ty: &IRTy) -> Result<UntypedPattern, UError> {
+        let location = no_span();
+
+        let result = match pattern {
+            IRPattern::Var(var) => {
+                self.env.borrow_mut().bind(var.ident.clone(), ty.clone());
+
+                UntypedPattern::Var {
+                    location,
+                    name: var.ident.clone(),
+                }
+            }
+            IRPattern::Discard => UntypedPattern::Discard {
+                name: "_".to_string(),
+                location,
+            },
+            IRPattern::Object(props) => {
+                let typedef = self.typedef_of(ty);
+                let (type_name, fields) = match typedef {
+                    Some(typedef) if typedef.discriminant.is_none() => {
+                        let constructor = typedef.constructors.into_iter().next();
+                        let fields = constructor.map(|c| c.fields).unwrap_or_default();
+                        (typedef.name.ident, fields)
+                    }
+                    _ => return Err(UError::ObjectPatternNotSupported(format!("{:?}", ty))),
+                };
+
+                let mut arguments = Vec::with_capacity(props.len());
+                for prop in props {
+                    let label = prop.name.ident.clone();
+                    let field = fields
+                        .iter()
+                        .find(|field| field.name.ident == label)
+                        .ok_or_else(|| UError::UnknownPatternField {
+                            type_name: type_name.clone(),
+                            field: label.clone(),
+                        })?;
+
+                    arguments.push(CallArg {
+                        label: Some(label),
+                        location,
+                        value: self.make_pattern(&prop.pattern, &field.ty)?,
+                    });
+                }
+
+                UntypedPattern::Constructor {
+                    is_record: true,
+                    location,
+                    name: type_name,
+                    arguments,
+                    module: None,
+                    constructor: (),
+                    with_spread: props.len() < fields.len(),
+                    tipo: (),
+                }
+            }
+            IRPattern::Array { elements, rest } => match ty {
+                IRTy::TupleTy(tys) => {
+                    if rest.is_some() || elements.len() > tys.len() {
+                        return Err(UError::ArrayPatternNotSupported(format!("{:?}", ty)));
+                    }
+
+                    // Elements left out are discarded
+                    let discard = IRPattern::Discard;
+                    let elements = elements.iter().chain(std::iter::repeat(&discard));
+
+                    let mut elems = Vec::with_capacity(tys.len());
+                    for (element, ty) in elements.zip(tys) {
+                        elems.push(self.make_pattern(element, ty)?);
+                    }
+
+                    UntypedPattern::Tuple { location, elems }
+                }
+                IRTy::ListTy(_) | IRTy::UnknownTy => {
+                    let elem_ty = match ty {
+                        IRTy::ListTy(elem_ty) => elem_ty.as_ref().clone(),
+                        _ => IRTy::UnknownTy,
+                    };
+
+                    let mut patterns = Vec::with_capacity(elements.len());
+                    for element in elements {
+                        patterns.push(self.make_pattern(element, &elem_ty)?);
+                    }
+
+                    // Without a rest element, there must be no more elements
+                    let tail = match rest {
+                        Some(rest) => self.make_pattern(rest, ty)?,
+                        None => UntypedPattern::List {
+                            location,
+                            elements: vec![],
+                            tail: None,
+                        },
+                    };
+
+                    UntypedPattern::List {
+                        location,
+                        elements: patterns,
+                        tail: Some(Box::new(tail)),
+                    }
+                }
+                _ => return Err(UError::ArrayPatternNotSupported(format!("{:?}", ty))),
+            },
+        };
+
+        Ok(result)
+    }
+
+    /// `if`/`else if`/`else`. Each branch ends with `yielded` when the statement is a join,
+    /// see [crate::ir_ssa], in which case the `else` may be missing.
+    fn make_if(
+        &self,
+        ifstmt: &IRIfStmt,
+        yielded: Option<&UntypedExpr>,
+    ) -> Result<UntypedExpr, UError> {
+        // An `if` that does nothing, e.g. once its traces are stripped, is dropped
+        if yielded.is_none() && does_nothing(&ifstmt._then) && does_nothing(&ifstmt._else) {
+            return Ok(UntypedExpr::Sequence {
+                location: no_span(),
+                expressions: vec![],
+            });
+        }
+
+        // `if (a) {...} else if (b) {...} else {...}` arrives here as nested `IRIfStmt`s
+        // in the `else` position, which we flatten into the branches of one aiken `if`.
+        let mut branches = vec1![self.make_if_branch(ifstmt, yielded)?];
+        let mut _else = ifstmt._else.as_ref();
+        while let Some(else_if) = as_else_if(_else) {
+            branches.push(self.make_if_branch(else_if, yielded)?);
+            _else = else_if._else.as_ref();
+        }
+
+        let final_else = match yielded {
+            // aiken has no `if` without an `else`
+            None if matches!(_else, IR::BlockStmt(IRBlockStmt { body }) if body.is_empty()) => {
+                return Err(UError::ExpectingElse)
+            }
+            _ => {
+                let final_else = self.visit_ir(_else)?.to_expr_result()?;
+                with_yield(final_else, yielded)
+            }
+        };
+
+        Ok(UntypedExpr::If {
+            location: no_span(),
+            branches,
+            final_else: Box::new(final_else),
+        })
+    }
+
+    fn make_switch(
+        &self,
+        switchstmt: &IRSwitchStmt,
+        yielded: Option<&UntypedExpr>,
+    ) -> Result<UntypedExpr, UError> {
+        match self.as_union_discriminant(&switchstmt.discriminant) {
+            Some((value, typedef)) => self.make_when(value, &typedef, switchstmt, yielded),
+            None => self.make_if_chain(switchstmt, yielded),
+        }
+    }
+
+    /// A `switch` on a tagged union becomes a `when`, with one clause per `case`.
+    /// In each `case`, the fields that the constructors of its tags have in common are bound
+    /// to variables, so that they can be accessed as in Typescript.
+    fn make_when(
+        &self,
+        value: String,
+        typedef: &IRTypeDef,
+        switchstmt: &IRSwitchStmt,
+        yielded: Option<&UntypedExpr>,
+    ) -> Result<UntypedExpr, UError> {
+        let location = no_span();
+        let type_name = typedef.name.ident.clone();
+
+        let mut clauses = Vec::with_capacity(switchstmt.cases.len());
+        let mut covered = Vec::new();
+        let mut has_default = false;
+        for case in &switchstmt.cases {
+            let mut constructors = Vec::with_capacity(case.tests.len());
+            for test in &case.tests {
+                let tag = match test {
+                    IRExpr::Literal(IRLiteral::String(tag)) => tag,
+                    _ => return Err(UError::SwitchCaseNotATag),
+                };
+                let constructor =
+                    typedef
+                        .constructor_of_tag(tag)
+                        .ok_or_else(|| UError::UnknownSwitchCase {
+                            type_name: type_name.clone(),
+                            case: tag.clone(),
+                        })?;
+
+                covered.push(tag.clone());
+                constructors.push(constructor);
+            }
+
+            let (pattern, alternative_patterns, then) = match constructors.as_slice() {
+                [] => {
+                    has_default = true;
+
+                    let pattern = UntypedPattern::Discard {
+                        name: "_".to_string(),
+                        location,
+                    };
+                    let then = self.visit_blockstmt(&case.body)?.to_expr_result()?;
+
+                    (pattern, vec![], with_yield(then, yielded))
+                }
+                constructors => {
+                    // Alternatives must bind the same variables, so only the fields that
+                    // all the constructors have, with the same type, are bound
+                    let shared: Vec<&IRNameTy> = constructors[0]
+                        .fields
+                        .iter()
+                        .filter(|field| {
+                            constructors.iter().all(|constructor| {
+                                constructor.fields.iter().any(|other| {
+                                    other.name.ident == field.name.ident && other.ty == field.ty
+                                })
+                            })
+                        })
+                        .collect();
+
+                    // The fields must not shadow the variables of the branch, nor the
+                    // fields of the unions narrowed around it
+                    let mut narrowed = self.narrowed.borrow().clone();
+                    let mut used = names(&case.body.body);
+                    used.extend(narrowed.values().cloned());
+                    let mut bound = Vec::with_capacity(shared.len());
+                    for field in &shared {
+                        let label = field.name.ident.clone();
+                        let name = fresh_name(&format!("{}_{}", value, label), &used);
+                        used.insert(name.clone());
+                        narrowed.insert((value.clone(), label.clone()), name.clone());
+                        bound.push((label, name));
+                    }
+
+                    let mut patterns = constructors.iter().map(|constructor| {
+                        let arguments = bound
+                            .iter()
+                            .map(|(label, name)| CallArg {
+                                label: Some(label.clone()),
+                                location,
+                                value: UntypedPattern::Var {
+                                    location,
+                                    name: name.clone(),
+                                },
+                            })
+                            .collect();
+
+                        UntypedPattern::Constructor {
+                            is_record: !constructor.fields.is_empty(),
+                            location,
+                            name: constructor.name.ident.clone(),
+                            arguments,
+                            module: None,
+                            constructor: (),
+                            with_spread: constructor.fields.len() > shared.len(),
+                            tipo: (),
+                        }
+                    });
+
+                    // Safe, there is at least one constructor
+                    let pattern = patterns.next().unwrap();
+                    let alternative_patterns = patterns.map(|pattern| vec![pattern]).collect();
+
+                    let outer = self.narrowed.replace(narrowed);
+                    let then = self.visit_blockstmt(&case.body);
+                    self.narrowed.replace(outer);
+
+                    (
+                        pattern,
+                        alternative_patterns,
+                        with_yield(then?.to_expr_result()?, yielded),
+                    )
+                }
+            };
+
+            clauses.push(Clause {
+                location,
+                pattern: vec![pattern],
+                alternative_patterns,
+                guard: None,
+                then,
+            });
+        }
+
+        if !has_default {
+            let missing: Vec<String> = typedef
+                .constructors
+                .iter()
+                .filter_map(|constructor| constructor.tag.clone())
+                .filter(|tag| !covered.contains(tag))
+                .collect();
+
+            match yielded {
+                _ if missing.is_empty() => {}
+                // The variables are left unchanged for the missing tags
+                Some(yielded) => clauses.push(Clause {
+                    location,
+                    pattern: vec![UntypedPattern::Discard {
+                        name: "_".to_string(),
+                        location,
+                    }],
+                    alternative_patterns: vec![],
+                    guard: None,
+                    then: yielded.clone(),
+                }),
+                None => {
+                    return Err(UError::NonExhaustiveSwitch {
+                        type_name,
+                        missing,
+                        span: switchstmt.span,
+                    })
+                }
+            }
+        }
+
+        Ok(UntypedExpr::When {
+            location,
+            subjects: vec![UntypedExpr::Var {
+                location,
+                name: value,
+            }],
+            clauses,
+        })
+    }
+
+    /// Any other `switch` becomes an `if` chain that compares the discriminant with each `case`.
+    fn make_if_chain(
+        &self,
+        switchstmt: &IRSwitchStmt,
+        yielded: Option<&UntypedExpr>,
+    ) -> Result<UntypedExpr, UError> {
+        let location = no_span();
+        let discriminant = self
+            .visit_expr(switchstmt.discriminant.as_ref())?
+            .to_expr_result()?;
+
+        let mut branches = Vec::with_capacity(switchstmt.cases.len());
+        let mut final_else = None;
+        for case in &switchstmt.cases {
+            let body = self.visit_blockstmt(&case.body)?.to_expr_result()?;
+            let body = with_yield(body, yielded);
+
+            let mut condition = None;
+            for test in &case.tests {
+                let test = self.visit_expr(test)?.to_expr_result()?;
+                let eq = UntypedExpr::BinOp {
+                    location,
+                    name: BinOp::Eq,
+                    left: Box::new(discriminant.clone()),
+                    right: Box::new(test),
+                };
+
+                condition = Some(match condition {
+                    None => eq,
+                    Some(condition) => UntypedExpr::BinOp {
+                        location,
+                        name: BinOp::Or,
+                        left: Box::new(condition),
+                        right: Box::new(eq),
+                    },
+                });
+            }
+
+            match condition {
+                None => final_else = Some(body),
+                Some(condition) => branches.push(IfBranch {
+                    condition,
+                    body,
+                    location,
+                }),
+            }
+        }
+
+        let final_else = final_else
+            .or_else(|| yielded.cloned())
+            .ok_or(UError::SwitchWithoutDefault)?;
+        match Vec1::try_from_vec(branches) {
+            Ok(branches) => Ok(UntypedExpr::If {
+                location,
+                branches,
+                final_else: Box::new(final_else),
+            }),
+            // Only a `default`
+            Err(_) => Ok(final_else),
+        }
+    }
+
+    /// The name of the next loop helper, skipping those already taken by the user
+    fn make_loop_name(&self) -> String {
+        let globals = self.globals.borrow();
+        let def_names = self.def_names.borrow();
+        loop {
+            let count = self.loops.get();
+            self.loops.set(count + 1);
+
+            let name = format!("{}_loop_{}", self.current_def.borrow(), count);
+            if !globals.contains(&name) && !def_names.contains(&name) {
+                return name;
+            }
+        }
+    }
+
+    /// The variables threaded through the helper function of a loop: first the state,
+    /// i.e. the variables the body assigns to, then the other variables of the enclosing
+    /// function used by the loop, out of `free`.
+    fn loop_vars(&self, body: &IRBlockStmt, free: Vars) -> Result<Vec<String>, UError> {
+        let state = loop_state(body);
+        let globals = self.globals.borrow();
+        let free = free
+            .into_iter()
+            .filter(|var| !state.contains(var) && !globals.contains(var));
+
+        Ok(state.iter().cloned().chain(free).collect())
+    }
+
+    /// The parameters of the helper function of a loop for the `threaded` variables.
+    fn loop_params(&self, threaded: &[String]) -> Vec<IRNameTy> {
+        let env = self.env.borrow();
+        threaded
+            .iter()
+            .map(|var| IRNameTy {
+                name: Box::new(IRIdent { ident: var.clone() }),
+                ty: env.lookup(var),
+            })
+            .collect()
+    }
+
+    /// Lowers the body of a loop, followed by the recursive call for the next iteration,
+    /// in the scope of the helper function.
+    fn make_loop_body(
+        &self,
+        name: &str,
+        body: &IRBlockStmt,
+        params: &[IRNameTy],
+        args: Vec<IRExpr>,
+    ) -> Result<UntypedExpr, UError> {
+        let next = IRApply {
+            name: Box::new(IRIdent {
+                ident: name.to_string(),
+            }),
+            args,
+            spread: None,
+        };
+        let mut body = body.clone();
+        body.body.push(IR::ReturnStmt(IRReturnStmt {
+            expr: Some(IRExpr::Apply(Box::new(next))),
+        }));
+        // What follows a `throw` in the body moves to the other branches
+        let body = normalize_body(body);
+
+        // The helper is a separate function, the narrowed variables are not in its scope
+        let narrowed = self.narrowed.take();
+        let body = self.in_scope(|| {
+            self.bind_params(params);
+            self.visit_blockstmt(&body)
+        });
+        self.narrowed.replace(narrowed);
+
+        body?.to_expr_result()
+    }
+
+    fn push_loop_helper(&self, name: &str, params: &[IRNameTy], body: UntypedExpr) {
+        let helper = UntypedDefinition::Fn(Function {
+            arguments: self.make_arguments(params),
+            body,
+            doc: None,
+            location: no_span(),
+            name: name.to_string(),
+            public: false,
+            return_annotation: None,
+            return_type: (),
+            end_position: 0,
+        });
+
+        self.helpers.borrow_mut().push(helper);
+    }
+
+    /// The loop itself, `let state = name(args.., threaded..)`.
+    fn make_loop_call(
+        &self,
+        name: &str,
+        mut args: Vec<UntypedExpr>,
+        state: &[String],
+        threaded: &[String],
+    ) -> Result<UntypedExpr, UError> {
+        let location = no_span();
+
+        for var in threaded {
+            args.push(
+                self.visit_ident(&IRIdent { ident: var.clone() })?
+                    .to_expr_result()?,
+            );
+        }
+        let arguments = args
+            .into_iter()
+            .map(|value| CallArg {
+                label: None,
+                location,
+                value,
+            })
+            .collect();
+
+        Ok(UntypedExpr::Assignment {
+            location,
+            value: Box::new(UntypedExpr::Call {
+                arguments,
+                fun: Box::new(UntypedExpr::Var {
+                    location,
+                    name: name.to_string(),
+                }),
+                location,
+            }),
+            pattern: vars_pattern(state),
+            kind: AssignmentKind::Let,
+            annotation: None,
+        })
+    }
+
+    /// Rewrites `for (const item of list) { body }` to a recursive helper function
+    ///
+    /// ```aiken
+    /// fn f_loop_0(item_list, state.., free..) {
+    ///   when item_list is {
+    ///     [] -> state
+    ///     [item, ..item_rest] -> { body; f_loop_0(item_rest, state.., free..) }
+    ///   }
+    /// }
+    /// ```
+    ///
+    /// where `state` are the variables the body assigns to, and `free` the other variables
+    /// of the enclosing function it uses. The loop itself becomes `let state = f_loop_0(list, ..)`.
+    fn make_for_of(&self, forofstmt: &IRForOfStmt) -> Result<UntypedExpr, UError> {
+        let location = no_span();
+        let item = forofstmt.item.ident.clone();
+
+        let mut free = FreeVars::default().visit_blockstmt(&forofstmt.body);
+        free.remove(&item);
+        let threaded = self.loop_vars(&forofstmt.body, free)?;
+        let state = loop_state(&forofstmt.body).into_iter().collect::<Vec<_>>();
+
+        // The list and its tail must not shadow the variables the body uses
+        let mut used = names(&forofstmt.body.body);
+        used.extend(threaded.iter().cloned());
+        used.insert(item.clone());
+        let item_list = fresh_name(&format!("{}_list", item), &used);
+        used.insert(item_list.clone());
+        let item_rest = fresh_name(&format!("{}_rest", item), &used);
+
+        let name = self.make_loop_name();
+        let list_ty = self.type_of(&forofstmt.list);
+        let item_ty = match &list_ty {
+            IRTy::ListTy(elem_ty) => elem_ty.as_ref().clone(),
+            _ => IRTy::UnknownTy,
+        };
+
+        let mut params = vec![IRNameTy {
+            name: Box::new(IRIdent {
+                ident: item_list.clone(),
+            }),
+            ty: list_ty,
+        }];
+        params.extend(self.loop_params(&threaded));
+
+        let mut args = vec![IRExpr::Identifier(IRIdent {
+            ident: item_rest.clone(),
+        })];
+        args.extend(
+            threaded
+                .iter()
+                .map(|var| IRExpr::Identifier(IRIdent { ident: var.clone() })),
+        );
+
+        let mut body_params = params.clone();
+        body_params.push(IRNameTy {
+            name: Box::new(IRIdent {
+                ident: item.clone(),
+            }),
+            ty: item_ty,
+        });
+        let body = self.make_loop_body(&name, &forofstmt.body, &body_params, args)?;
+
+        let empty = UntypedPattern::List {
+            location,
+            elements: vec![],
+            tail: None,
+        };
+        let cons = UntypedPattern::List {
+            location,
+            elements: vec![UntypedPattern::Var {
+                location,
+                name: item,
+            }],
+            tail: Some(Box::new(UntypedPattern::Var {
+                location,
+                name: item_rest,
+            })),
+        };
+        let clause = |pattern, then| Clause {
+            location,
+            pattern: vec![pattern],
+            alternative_patterns: vec![],
+            guard: None,
+            then,
+        };
+
+        let when = UntypedExpr::When {
+            location,
+            subjects: vec![UntypedExpr::Var {
+                location,
+                name: item_list,
+            }],
+            clauses: vec![clause(empty, vars_expr(&state)), clause(cons, body)],
+        };
+        self.push_loop_helper(&name, &params, when);
+
+        let list = self.visit_expr(&forofstmt.list)?.to_expr_result()?;
+        self.make_loop_call(&name, vec![list], &state, &threaded)
+    }
+
+    /// Rewrites `while (test) { body }` to a recursive helper function
+    ///
+    /// ```aiken
+    /// fn f_loop_0(state.., free..) {
+    ///   if test { body; f_loop_0(state.., free..) } else { state }
+    /// }
+    /// ```
+    ///
+    /// see [Self::make_for_of]. Note the loop must terminate for the script to succeed.
+    fn make_while(&self, whilestmt: &IRWhileStmt) -> Result<UntypedExpr, UError> {
+        let location = no_span();
+
+        let mut free = FreeVars::default().visit_blockstmt(&whilestmt.body);
+        free.extend(FreeVars::default().visit_expr(&whilestmt.test));
+        let threaded = self.loop_vars(&whilestmt.body, free)?;
+        let state = loop_state(&whilestmt.body).into_iter().collect::<Vec<_>>();
+
+        let name = self.make_loop_name();
+        let params = self.loop_params(&threaded);
+        let args = threaded
+            .iter()
+            .map(|var| IRExpr::Identifier(IRIdent { ident: var.clone() }))
+            .collect();
+
+        let body = self.make_loop_body(&name, &whilestmt.body, &params, args)?;
+        let condition = self.in_scope(|| {
+            self.bind_params(&params);
+            self.visit_condition(&whilestmt.test)
+        })?;
+
+        let branch = IfBranch {
+            condition,
+            body,
+            location,
+        };
+        let _if = UntypedExpr::If {
+            location,
+            branches: vec1![branch],
+            final_else: Box::new(vars_expr(&state)),
+        };
+        self.push_loop_helper(&name, &params, _if);
+
+        self.make_loop_call(&name, vec![], &state, &threaded)
+    }
+
+    fn make_arguments(&self, params: &[IRNameTy]) -> Vec<Arg<()>> {
+        let mut arguments = Vec::with_capacity(params.len());
+        for param in params {
+            let location = no_span();
+            let name = param.name.as_ref().ident.clone();
+            let arg_name = ArgName::Named { name, location };
+
+            let arg = Arg {
+                arg_name,
+                location,
+                annotation: ir_ty_to_annotation(&param.ty),
+                tipo: (),
+            };
+
+            arguments.push(arg);
+        }
+
+        arguments
+    }
+
+    /// `let param = when param is { Some(option_value) -> option_value  None -> default }`
+    /// for each parameter with a default value, which is computed in the scope of the function
+    /// rather than in the one of each caller.
+    fn make_defaults(
+        &self,
+        params: &[IRNameTy],
+        arity: &IRArity,
+    ) -> Result<Vec<UntypedExpr>, UError> {
+        let location = no_span();
+
+        let mut defaults = Vec::new();
+        for (param, default) in params.iter().zip(&arity.defaults) {
+            let default = match default {
+                Some(default) => self.visit_expr_expecting(default, &param.ty)?,
+                None => continue,
+            };
+            let name = param.name.ident.clone();
+            let var = |name: &str| UntypedExpr::Var {
+                location,
+                name: name.to_string(),
+            };
+            let value_of_some = match &param.ty {
+                IRTy::OptionTy(_) => some(var(OPTION_VALUE)),
+                _ => var(OPTION_VALUE),
+            };
+            let value = self.make_option_when(var(&name), value_of_some, default);
+
+            defaults.push(UntypedExpr::Assignment {
+                location,
+                value: Box::new(value),
+                pattern: UntypedPattern::Var { location, name },
+                kind: AssignmentKind::Let,
+                annotation: None,
+            });
+        }
+
+        Ok(defaults)
+    }
+
+    /// The body of a function, preceded by the default values of its parameters.
+    /// A body that does nothing, e.g. a `void` function that only logs once its traces are
+    /// stripped, is the unit value.
+    fn make_function_body(
+        &self,
+        params: &[IRNameTy],
+        arity: &IRArity,
+        fun_ty: &IRFunTy,
+        body: &IRBlockStmt,
+    ) -> Result<UntypedExpr, UError> {
+        self.in_function(params, fun_ty, || {
+            let mut expressions = self.make_defaults(params, arity)?;
+            let body = match self.visit_blockstmt(body)?.to_expr_result()? {
+                UntypedExpr::Sequence { expressions, .. } if expressions.is_empty() => {
+                    UntypedExpr::Var {
+                        location: no_span(),
+                        name: UNIT.to_string(),
+                    }
+                }
+                body => body,
+            };
+            if expressions.is_empty() {
+                return Ok(body);
+            }
+
+            match body {
+                UntypedExpr::Sequence {
+                    expressions: body, ..
+                } => expressions.extend(body),
+                body => expressions.push(body),
+            }
+
+            Ok(UntypedExpr::Sequence {
+                location: no_span(),
+                expressions,
+            })
+        })
+    }
+
+    fn make_function(
+        &self,
+        name: String,
+        params: &[IRNameTy],
+        arity: &IRArity,
+        fun_ty: &IRFunTy,
+        body: &IRBlockStmt,
+    ) -> UResult {
+        if VALIDATOR_NAMES.contains(&name.as_str()) && !arity.is_fixed() {
+            return Err(UError::ValidatorArityNotFixed(name));
+        }
+
+        let arguments = self.make_arguments(&received_params(params, arity));
+        let return_annotation = ir_ty_to_annotation(fun_ty.ret_ty.as_ref());
+        let body = self.make_function_body(params, arity, fun_ty, body)?;
+        let doc = None;
+        let location = no_span();
+
+        let result = UntypedDefinition::Fn(Function {
+            arguments,
+            body,
+            doc,
+            location,
+            name,
+            public: true,
+            return_annotation,
+            return_type: (),
+            end_position: 0,
+        });
+
+        Ok(result.into())
+    }
+}
+
+impl IRVisitor<UResult> for ModuleBuilderFromIR {
+    fn new() -> Self {
+        ModuleBuilderFromIR::default()
+    }
+
+    fn visit_script(&self, script: &IRScript) -> UResult {
+        // We allow only imports, types and function definitions at the top level.
+        // Why? Well the translation of IR to a random backend, such as Aiken's UntypedDeclaration
+        // and UntypedExpr nodes, is by necessity tied to the semantics of the backend.
+        // Also, we just need to demonstrate feasibility at the moment, not completeness or even
+        // correctness.
+
+        // Types and functions can be used before they are defined
+        for ir in &script.body {
+            match ir {
+                IR::TypeDef(typedef) => {
+                    self.type_defs
+                        .borrow_mut()
+                        .insert(typedef.name.ident.clone(), typedef.clone());
+                }
+                IR::FunDef(fundef) => {
+                    let ty = IRTy::FunTy(Box::new(fundef.fun_ty.clone()));
+                    self.env.borrow_mut().bind(fundef.name.ident.clone(), ty);
+                    self.globals.borrow_mut().insert(fundef.name.ident.clone());
+                    self.bind_arity(&fundef.name.ident, &fundef.params, &fundef.arity);
+                }
+                IR::VarDef(vardef) => {
+                    let ty = self.type_of(&vardef.value);
+                    self.env.borrow_mut().bind(vardef.name.ident.clone(), ty);
+                    self.globals.borrow_mut().insert(vardef.name.ident.clone());
+                    if let IRExpr::Lambda(lambda) = vardef.value.as_ref() {
+                        self.bind_arity(&vardef.name.ident, &lambda.params, &lambda.arity);
+                    }
+                }
+                IR::Import(import) => self.bind_import(import),
+                _ => {}
+            }
+        }
+
+        let mut defs = Vec::with_capacity(script.body.len());
+        for ir in &script.body {
+            if let IR::FunDef(IRFunDef { name, .. }) | IR::VarDef(IRVarDef { name, .. }) = ir {
+                self.current_def.replace(name.ident.clone());
+                self.def_names.replace(names(std::slice::from_ref(ir)));
+                self.loops.set(0);
+            }
+
+            let def = match ir {
+                IR::Import(v) => self.visit_import(v),
+                IR::TypeDef(v) => self.visit_typedef(v),
+                IR::FunDef(v) => self.visit_fundef(v),
+                // `const f = (a) => ...` is just another way to define a function
+                IR::VarDef(IRVarDef { name, value, .. }) => match value.as_ref() {
+                    IRExpr::Lambda(lambda) => self.make_function(
+                        name.ident.clone(),
+                        &lambda.params,
+                        &lambda.arity,
+                        &lambda.fun_ty,
+                        &lambda.body,
+                    ),
+                    _ => Err(UError::ExpectingFunDef),
+                },
+                _ => Err(UError::ExpectingFunDef),
+            }?;
+
+            defs.push(def.to_def_result()?);
+            // Along with the helpers for its loops
+            defs.append(&mut self.helpers.borrow_mut());
+        }
+
+        for prelude_fn in self.prelude.borrow().iter() {
+            defs.push(prelude_fn.definition());
+        }
+
+        if self.uses_builtin.get() {
+            defs.insert(
+                0,
+                UntypedDefinition::Use(Use {
+                    as_name: None,
+                    location: no_span(),
+                    module: vec!["aiken".to_string(), "builtin".to_string()],
+                    package: (),
+                    unqualified: vec![],
+                }),
+            );
+        }
+
+        Ok(defs.into())
+    }
+
+    fn visit_import(&self, import: &IRImport) -> UResult {
+        let module = import
+            .module
+            .as_ref()
+            .ok_or_else(|| UError::UnresolvedImport(import.src.clone()))?;
+
+        let import_name = |name: &str, as_name: Option<String>| UnqualifiedImport {
+            location: no_span(),
+            name: name.to_string(),
+            as_name,
+            layer: Default::default(),
+        };
+
+        let mut unqualified = Vec::with_capacity(import.specifiers.len());
+        for specifier in &import.specifiers {
+            let as_name = specifier.alias.as_ref().map(|alias| alias.ident.clone());
+            unqualified.push(import_name(&specifier.name.ident, as_name));
+
+            // Object literals of an imported type are built with its constructors
+            if let Some(IR::TypeDef(typedef)) = self.imported_def(module, &specifier.name.ident) {
+                for constructor in &typedef.constructors {
+                    if constructor.name.ident != specifier.name.ident {
+                        unqualified.push(import_name(&constructor.name.ident, None));
+                    }
+                }
+            }
+        }
+
+        let result = UntypedDefinition::Use(Use {
+            as_name: None,
+            location: no_span(),
+            module: module.split('/').map(String::from).collect(),
+            package: (),
+            unqualified,
+        });
+
+        Ok(result.into())
+    }
+
+    fn visit_fundef(&self, fundef: &IRFunDef) -> UResult {
+        let name = fundef.name.as_ref().ident.clone();
+
+        self.make_function(
+            name,
+            &fundef.params,
+            &fundef.arity,
+            &fundef.fun_ty,
+            &fundef.body,
+        )
+    }
+
+    fn visit_vardef(&self, vardef: &IRVarDef) -> UResult {
+        let location = no_span();
+        let name = vardef.name.as_ref().ident.clone();
+
+        let value = self.visit_expr_expecting(vardef.value.as_ref(), &vardef.ty)?;
+        let value = Box::new(value);
+
+        let ty = match &vardef.ty {
+            IRTy::UnknownTy => self.type_of(&vardef.value),
+            ty => ty.clone(),
+        };
+        self.env.borrow_mut().bind(name.clone(), ty);
+        if let IRExpr::Lambda(lambda) = vardef.value.as_ref() {
+            self.bind_arity(&name, &lambda.params, &lambda.arity);
+        }
+
+        let pattern = UntypedPattern::Var { location, name };
+
+        let result = UntypedExpr::Assignment {
+            location,
+            value,
+            pattern,
+            kind: AssignmentKind::Let,
+            annotation: None,
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_patdef(&self, patdef: &IRPatDef) -> UResult {
+        let value = self.visit_expr_expecting(patdef.value.as_ref(), &patdef.ty)?;
+
+        let ty = match &patdef.ty {
+            IRTy::UnknownTy => self.type_of(&patdef.value),
+            ty => ty.clone(),
+        };
+        let pattern = self.make_pattern(&patdef.pattern, &ty)?;
+        // `let` only takes patterns that always match, `assert` fails when the pattern does not
+        let kind = if is_refutable(&pattern) {
+            AssignmentKind::Assert
+        } else {
+            AssignmentKind::Let
+        };
+
+        let result = UntypedExpr::Assignment {
+            location: no_span(),
+            value: Box::new(value),
+            pattern,
+            kind,
+            annotation: None,
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_typedef(&self, typedef: &IRTypeDef) -> UResult {
+        let location = no_span();
+        let type_name = typedef.name.ident.clone();
+
+        let mut constructors = Vec::with_capacity(typedef.constructors.len());
+        for constructor in &typedef.constructors {
+            let mut arguments = Vec::with_capacity(constructor.fields.len());
+            for field in &constructor.fields {
+                let label = field.name.ident.clone();
+                let annotation = ir_ty_to_annotation(&field.ty).ok_or_else(|| {
+                    UError::FieldTypeNotSupported {
+                        type_name: type_name.clone(),
+                        field: label.clone(),
+                    }
+                })?;
+
+                arguments.push(RecordConstructorArg {
+                    label: Some(label),
+                    annotation,
+                    location,
+                    tipo: (),
+                    doc: None,
+                });
+            }
+
+            constructors.push(RecordConstructor {
+                location,
+                name: constructor.name.ident.clone(),
+                arguments,
+                documentation: None,
+                sugar: false,
+            });
+        }
+
+        let result = UntypedDefinition::DataType(DataType {
+            constructors,
+            doc: None,
+            location,
+            name: type_name,
+            opaque: false,
+            parameters: typedef.type_params.clone(),
+            public: true,
+            typed_parameters: vec![],
+        });
+
+        Ok(result.into())
+    }
+
+    fn visit_retstmt(&self, retstmt: &IRReturnStmt) -> UResult {
+        // Function bodies have been normalised (see `ir_normalize`),
+        // so the `return` is always the last thing evaluated.
+        match &retstmt.expr {
+            None => Err(UError::ExpectingExpr),
+            Some(v) => {
+                let ret_ty = self.ret_tys.borrow().last().cloned();
+                let expr = self.visit_expr_expecting(v, &ret_ty.unwrap_or(IRTy::UnknownTy))?;
+                Ok(expr.into())
+            }
+        }
+    }
+
+    /// `throw new Error("message")` is `error @"message"`, which traces the message
+    fn visit_throwstmt(&self, throwstmt: &IRThrowStmt) -> UResult {
+        let label = match self.options.traces {
+            Traces::Keep => throwstmt.message.clone(),
+            Traces::Strip => None,
+        };
+        let result = UntypedExpr::ErrorTerm {
+            location: no_span(),
+            label,
+        };
+
+        Ok(result.into())
+    }
+
+    /// `console.log(...)` is `let _ = builtin.trace(message, True)`, which is evaluated like
+    /// the `let`s of the loops that only check something
+    fn visit_tracestmt(&self, tracestmt: &IRTraceStmt) -> UResult {
+        let message = self.visit_expr(&tracestmt.message)?.to_expr_result()?;
+
+        let result = UntypedExpr::Assignment {
+            location: no_span(),
+            value: Box::new(self.builtin_call("trace", vec![message, vars_expr(&[])])),
+            pattern: vars_pattern(&[]),
+            kind: AssignmentKind::Let,
+            annotation: None,
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_blockstmt(&self, blockstmt: &IRBlockStmt) -> UResult {
+        let location = no_span();
+        let body = &blockstmt.body;
+        let mut expressions = Vec::with_capacity(body.len());
+
+        self.in_scope(|| -> Result<(), UError> {
+            for (i, ir) in body.iter().enumerate() {
+                match ir {
+                    // A nested block has its own scope. One that assigns to the variables of
+                    // this scope arrives as a join, so the others can only matter at the end,
+                    // where whatever follows a `return` in them has been moved.
+                    // Blocks left empty, e.g. once their traces are stripped, do not matter
+                    IR::BlockStmt(_) if i + 1 < body.len() && !may_return(ir) => {}
+                    IR::BlockStmt(_) if does_nothing(ir) => {}
+                    IR::BlockStmt(_) => expressions.push(self.visit_ir(ir)?.to_expr_result()?),
+                    // The statements lowered to several `let`s, e.g. destructuring,
+                    // are spliced in, so that their variables are visible to what follows
+                    ir => match self.visit_ir(ir)?.to_expr_result()? {
+                        UntypedExpr::Sequence {
+                            expressions: nested,
+                            ..
+                        } => expressions.extend(nested),
+                        expr => expressions.push(expr),
+                    },
+                }
+            }
+
+            Ok(())
+        })?;
+
+        let result = UntypedExpr::Sequence {
+            location: no_span(),
+            expressions,
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_ifstmt(&self, ifstmt: &IRIfStmt) -> UResult {
+        let result = self.make_if(ifstmt, None)?;
+
+        Ok(result.into())
+    }
+
+    fn visit_switchstmt(&self, switchstmt: &IRSwitchStmt) -> UResult {
+        let result = self.make_switch(switchstmt, None)?;
+
+        Ok(result.into())
+    }
+
+    fn visit_joinstmt(&self, joinstmt: &IRJoinStmt) -> UResult {
+        // `let vars = if .. { ..; vars } else { ..; vars }`, or `let vars = { ..; vars }`
+        let vars: Vec<String> = joinstmt.vars.iter().map(|var| var.ident.clone()).collect();
+        let yielded = vars_expr(&vars);
+
+        let value = match joinstmt.stmt.as_ref() {
+            IR::IfStmt(ifstmt) => self.make_if(ifstmt, Some(&yielded))?,
+            IR::SwitchStmt(switchstmt) => self.make_switch(switchstmt, Some(&yielded))?,
+            IR::BlockStmt(blockstmt) => {
+                let body = self.visit_blockstmt(blockstmt)?.to_expr_result()?;
+                with_yield(body, Some(&yielded))
+            }
+            _ => return Err(UError::ExpectingExpr),
+        };
+
+        let result = UntypedExpr::Assignment {
+            location: no_span(),
+            value: Box::new(value),
+            pattern: vars_pattern(&vars),
+            kind: AssignmentKind::Let,
+            annotation: None,
+        };
+
+        Ok(result.into())
+    }
+
+    /// `let name = fn(vars..) { body }`, see [crate::ir_normalize], where the variables have
+    /// the types they have here. There is no return annotation, since the continuation of
+    /// the body of a loop yields the state of the loop.
+    fn visit_continuationdef(&self, contdef: &IRContinuationDef) -> UResult {
+        let location = no_span();
+        let vars: Vec<String> = contdef.vars.iter().map(|var| var.ident.clone()).collect();
+        let params = self.loop_params(&vars);
+        let ret_ty = self.ret_tys.borrow().last().cloned();
+        let fun_ty = IRFunTy {
+            type_params: vec![],
+            param_tys: params.iter().map(|param| param.ty.clone()).collect(),
+            ret_ty: Box::new(ret_ty.unwrap_or(IRTy::UnknownTy)),
+        };
+
+        let arguments = self.make_arguments(&params);
+        let body = self.in_function(&params, &fun_ty, || self.visit_blockstmt(&contdef.body))?;
+        let value = UntypedExpr::Fn {
+            location,
+            is_capture: false,
+            arguments,
+            body: Box::new(body.to_expr_result()?),
+            return_annotation: None,
+        };
+
+        let name = contdef.name.ident.clone();
+        let ty = IRTy::FunTy(Box::new(fun_ty));
+        self.env.borrow_mut().bind(name.clone(), ty);
+
+        let result = UntypedExpr::Assignment {
+            location,
+            value: Box::new(value),
+            pattern: UntypedPattern::Var { location, name },
+            kind: AssignmentKind::Let,
+            annotation: None,
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_assignstmt(&self, assignstmt: &IRAssignStmt) -> UResult {
+        // Variables are immutable in aiken, the variable is shadowed by its new value instead
+        let location = no_span();
+        let ty = self.env.borrow().lookup(&assignstmt.name.ident);
+        let value = self.visit_expr_expecting(&assignstmt.value, &ty)?;
+
+        let result = UntypedExpr::Assignment {
+            location,
+            value: Box::new(value),
+            pattern: UntypedPattern::Var {
+                location,
+                name: assignstmt.name.ident.clone(),
+            },
+            kind: AssignmentKind::Let,
+            annotation: None,
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_forofstmt(&self, forofstmt: &IRForOfStmt) -> UResult {
+        let result = self.make_for_of(forofstmt)?;
+
+        Ok(result.into())
+    }
+
+    fn visit_whilestmt(&self, whilestmt: &IRWhileStmt) -> UResult {
+        let result = self.make_while(whilestmt)?;
+
+        Ok(result.into())
+    }
+
+    fn visit_exprstmt(&self, exprstmt: &IRExprStmt) -> UResult {
+        let expr = self.visit_expr(&exprstmt.expr)?.to_expr_result()?;
+
+        let result = UntypedExpr::Sequence {
+            location: no_span(),
+            expressions: vec![expr],
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_literal(&self, literal: &IRLiteral) -> UResult {
+        let location = no_span();
+
+        let result = match literal {
+            IRLiteral::Boolean(v) => UntypedExpr::Var {
+                location,
+                name: (if *v { "True" } else { "False" }).to_string(),
+            },
+            IRLiteral::Float64(v) => UntypedExpr::Int {
+                location,
+                value: self
+                    .options
+                    .numbers
+                    .number_literal(*v)
+                    .ok_or_else(|| UError::NumberNotRepresentable(v.to_string()))?
+                    .to_string(),
+            },
+            IRLiteral::String(v) => UntypedExpr::String {
+                location,
+                value: v.clone(),
+            },
+            IRLiteral::BigInt(v) => UntypedExpr::Int {
+                location,
+                value: v.to_string(),
+            },
+            IRLiteral::ByteArray(bytes) => UntypedExpr::ByteArray {
+                location,
+                bytes: bytes.clone(),
+            },
+            IRLiteral::Undefined => none(),
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_ident(&self, ident: &IRIdent) -> UResult {
+        let result = UntypedExpr::Var {
+            location: no_span(),
+            name: ident.ident.clone(),
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_unary_op(&self, unary_op: &IRUnaryExpr) -> UResult {
+        let location = no_span();
+        let value = || self.visit_expr(unary_op.expr.as_ref())?.to_expr_result();
+
+        let result = match unary_op.op {
+            IRUnOp::Not => UntypedExpr::Negate {
+                location,
+                value: Box::new(self.visit_condition(&unary_op.expr)?),
+            },
+            // There is no integer negation in aiken, so this becomes `0 - value`
+            IRUnOp::Minus => UntypedExpr::BinOp {
+                location,
+                name: BinOp::SubInt,
+                left: Box::new(UntypedExpr::Int {
+                    location,
+                    value: "0".to_string(),
+                }),
+                right: Box::new(value()?),
+            },
+            // Numbers are already numbers
+            IRUnOp::Plus => value()?,
+        };
+
+        Ok(result.into())
+    }
+
+    /// Binary operators are lowered according to the type of their operands: `+` on
+    /// strings appends, `==` compares any two values structurally and the other operators
+    /// are defined on integers, or on booleans for `&&` and `||`.
+    fn visit_binary_op(&self, binary_op: &IRBinaryExpr) -> UResult {
+        let location = no_span();
+        let op = binary_op.op.clone();
+
+        if let IRBinOp::NullishCoalescing = op {
+            return self.make_nullish_coalescing(binary_op).map(UNode::from);
+        }
+
+        let ty = self.operand_ty(binary_op)?;
+        let unsupported = || UError::OperatorNotSupported {
+            op: op.clone(),
+            ty: ty.clone(),
+        };
+        let is_number = matches!(ty, IRTy::Float64Ty | IRTy::BigIntTy | IRTy::UnknownTy);
+
+        match (&op, &ty) {
+            (IRBinOp::EqEq | IRBinOp::NotEq, IRTy::FunTy(_)) => return Err(unsupported()),
+            (IRBinOp::EqEq | IRBinOp::NotEq, _) => {}
+            (IRBinOp::LogicalAnd | IRBinOp::LogicalOr, IRTy::BooleanTy | IRTy::UnknownTy) => {}
+            (IRBinOp::LogicalAnd | IRBinOp::LogicalOr, _) => return Err(unsupported()),
+            (IRBinOp::Add, IRTy::StringTy) => {
+                let left = self.visit_expr(&binary_op.left)?.to_expr_result()?;
+                let right = self.visit_expr(&binary_op.right)?.to_expr_result()?;
+
+                return Ok(self.builtin_call("append_string", vec![left, right]).into());
+            }
+            // Byte arrays are ordered lexicographically
+            (IRBinOp::Lt | IRBinOp::LtEq | IRBinOp::Gt | IRBinOp::GtEq, IRTy::ByteArrayTy) => {
+                let left = self.visit_expr(&binary_op.left)?.to_expr_result()?;
+                let right = self.visit_expr(&binary_op.right)?.to_expr_result()?;
+
+                let result = match op {
+                    IRBinOp::Lt => self.builtin_call("less_than_bytearray", vec![left, right]),
+                    IRBinOp::LtEq => {
+                        self.builtin_call("less_than_equals_bytearray", vec![left, right])
+                    }
+                    IRBinOp::Gt => self.builtin_call("less_than_bytearray", vec![right, left]),
+                    _ => self.builtin_call("less_than_equals_bytearray", vec![right, left]),
+                };
+
+                return Ok(result.into());
+            }
+            _ if is_number => {}
+            _ => return Err(unsupported()),
+        }
+
+        let is_bigint = ty == IRTy::BigIntTy;
+        let is_fixed_point = matches!(ty, IRTy::Float64Ty | IRTy::UnknownTy)
+            && matches!(self.options.numbers, NumericSemantics::FixedPoint { .. });
+
+        let operand = |expr: &IRExpr| match is_bigint {
+            true => self.visit_integer(expr),
+            false => self.visit_expr(expr)?.to_expr_result(),
+        };
+        let left = operand(binary_op.left.as_ref())?;
+        let right = operand(binary_op.right.as_ref())?;
+
+        let int_op = |name, left, right| UntypedExpr::BinOp {
+            location,
+            name,
+            left: Box::new(left),
+            right: Box::new(right),
+        };
+        let one = || UntypedExpr::Int {
+            location,
+            value: self.options.numbers.one().to_string(),
+        };
+
+        // JS division truncates towards zero, for `bigint`s and, by the time they are
+        // converted back to integers, for `number`s, unlike aiken's `/` and `%` which floor.
+        // Fixed-point numbers must also be rescaled after a multiplication or a division.
+        let result = match op {
+            IRBinOp::Div if is_fixed_point => {
+                let left = int_op(BinOp::MultInt, left, one());
+                self.builtin_call("quotient_integer", vec![left, right])
+            }
+            IRBinOp::Div => self.builtin_call("quotient_integer", vec![left, right]),
+            IRBinOp::Mod => self.builtin_call("remainder_integer", vec![left, right]),
+            IRBinOp::Mul if is_fixed_point => {
+                let product = int_op(BinOp::MultInt, left, right);
+                self.builtin_call("quotient_integer", vec![product, one()])
+            }
+            // Every factor would need rescaling
+            IRBinOp::Exp if is_fixed_point => return Err(unsupported()),
+            IRBinOp::Exp => self.prelude_call(PreludeFn::Pow, vec![left, right]),
+            _ => match ir_bin_op_to_bin_op(op.clone()) {
+                Some(name) => int_op(name, left, right),
+                None => return Err(unsupported()),
+            },
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_cond(&self, cond: &IRCondExpr) -> UResult {
+        self.make_cond(cond, &IRTy::UnknownTy).map(UNode::from)
+    }
+
+    fn visit_object(&self, object: &IRObjectLit) -> UResult {
+        self.make_object(object, &IRTy::UnknownTy).map(UNode::from)
+    }
+
+    fn visit_member(&self, member: &IRMember) -> UResult {
+        if let IRExpr::Identifier(value) = member.object.as_ref() {
+            let key = (value.ident.clone(), member.prop.ident.clone());
+            if let Some(name) = self.narrowed.borrow().get(&key) {
+                let result = UntypedExpr::Var {
+                    location: no_span(),
+                    name: name.clone(),
+                };
+
+                return Ok(result.into());
+            }
+        }
+
+        let object_ty = self.type_of(&member.object);
+        if let IRTy::NamedTy(type_name, _) = &object_ty {
+            let is_union = self
+                .type_defs
+                .borrow()
+                .get(type_name)
+                .map_or(false, |typedef| typedef.constructors.len() > 1);
+            if is_union {
+                return Err(UError::FieldOfUnion {
+                    type_name: type_name.clone(),
+                    field: member.prop.ident.clone(),
+                });
+            }
+        }
+
+        let container = self.visit_expr(member.object.as_ref())?.to_expr_result()?;
+
+        if object_ty == IRTy::ByteArrayTy && member.prop.ident == "length" {
+            return Ok(self
+                .builtin_call("length_of_bytearray", vec![container])
+                .into());
+        }
+
+        let result = UntypedExpr::FieldAccess {
+            location: no_span(),
+            label: member.prop.ident.clone(),
+            container: Box::new(container),
+        };
+
+        Ok(result.into())
+    }
+
+    /// `object?.prop` is
+    /// `when object is { Some(option_value) -> Some(option_value.prop)  None -> None }`,
+    /// without the `Some` if the property is optional itself.
+    fn visit_opt_member(&self, member: &IRMember) -> UResult {
+        let option = self.visit_expr(&member.object)?.to_expr_result()?;
+
+        let ty = match self.type_of(&member.object) {
+            IRTy::OptionTy(ty) => *ty,
+            _ => IRTy::UnknownTy,
+        };
+        let prop = IRMember {
+            object: Box::new(IRExpr::Identifier(IRIdent {
+                ident: OPTION_VALUE.to_string(),
+            })),
+            prop: member.prop.clone(),
+        };
+        let value = self.in_scope(|| {
+            self.env
+                .borrow_mut()
+                .bind(OPTION_VALUE.to_string(), ty.clone());
+            self.visit_member(&prop)
+        })?;
+        let value = match self.member_ty(ty, &member.prop.ident) {
+            IRTy::OptionTy(_) => value.to_expr_result()?,
+            _ => some(value.to_expr_result()?),
+        };
+
+        Ok(self.make_option_when(option, value, none()).into())
+    }
+
+    fn visit_array(&self, array: &IRArrayLit) -> UResult {
+        let mut elements = Vec::with_capacity(array.elements.len());
+        for element in &array.elements {
+            elements.push(self.visit_expr(element)?.to_expr_result()?);
+        }
+
+        let result = UntypedExpr::List {
+            location: no_span(),
+            elements,
+            tail: None,
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_index(&self, index: &IRIndex) -> UResult {
+        // Lists have no constant-time indexing, so `[0]` only makes sense for tuples
+        if let IRTy::ListTy(_) = self.type_of(&index.object) {
+            return Err(UError::ListIndexNotSupported);
+        }
+
+        let tuple = self.visit_expr(&index.object)?.to_expr_result()?;
+
+        let result = UntypedExpr::TupleIndex {
+            location: no_span(),
+            index: index.index,
+            tuple: Box::new(tuple),
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_apply(&self, apply: &IRApply) -> UResult {
+        let location = no_span();
+        let arguments = self
+            .make_call_args(apply)?
+            .into_iter()
+            .map(|value| CallArg {
+                label: None,
+                location,
+                value,
+            })
+            .collect();
+
+        let fun = UntypedExpr::Var {
+            location,
+            name: apply.name.as_ref().ident.clone(),
+        };
+
+        let result = UntypedExpr::Call {
+            arguments,
+            fun: Box::new(fun),
+            location,
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_builtin_call(&self, builtin_call: &IRBuiltinCall) -> UResult {
+        let builtin = builtin_call.builtin;
+        let args = &builtin_call.args;
+
+        let arity_ok = match builtin {
+            IRBuiltin::MathAbs => args.len() == 1,
+            IRBuiltin::MathPow => args.len() == 2,
+            IRBuiltin::MathMin | IRBuiltin::MathMax => !args.is_empty(),
+            IRBuiltin::Slice => args.len() == 2 || args.len() == 3,
+            IRBuiltin::Concat => args.len() == 2,
+        };
+        if !arity_ok {
+            return Err(UError::BuiltinArity(builtin));
+        }
+
+        if let IRBuiltin::Slice | IRBuiltin::Concat = builtin {
+            return self.make_bytes_builtin(builtin, args);
+        }
+
+        if let IRBuiltin::MathPow = builtin {
+            let binary_op = IRBinaryExpr {
+                op: IRBinOp::Exp,
+                left: Box::new(args[0].clone()),
+                right: Box::new(args[1].clone()),
+            };
+
+            return self.visit_binary_op(&binary_op);
+        }
+
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            match self.type_of(arg) {
+                IRTy::Float64Ty | IRTy::BigIntTy | IRTy::UnknownTy => {}
+                ty => return Err(UError::BuiltinArgumentType { builtin, ty }),
+            }
+            values.push(self.visit_expr(arg)?.to_expr_result()?);
+        }
+
+        // `Math.min(a, b, c)` is `min(min(a, b), c)`
+        let mut values = values.into_iter();
+        let first = values.next().ok_or(UError::BuiltinArity(builtin))?;
+        let result = match builtin {
+            IRBuiltin::MathAbs => self.prelude_call(PreludeFn::Abs, vec![first]),
+            IRBuiltin::MathMin | IRBuiltin::MathMax => {
+                let prelude_fn = match builtin {
+                    IRBuiltin::MathMin => PreludeFn::Min,
+                    _ => PreludeFn::Max,
+                };
+                values.fold(first, |acc, value| {
+                    self.prelude_call(prelude_fn, vec![acc, value])
+                })
+            }
+            IRBuiltin::MathPow | IRBuiltin::Slice | IRBuiltin::Concat => {
+                unreachable!("lowered above")
+            }
+        };
+
+        Ok(result.into())
+    }
+
+    /// `bytes.slice(start, end)` is `builtin.slice_bytearray(start, end - start, bytes)` and
+    /// `bytes.concat(other)` is `builtin.append_bytearray(bytes, other)`.
+    /// The receiver must be known to be a byte array, since lists and strings have methods
+    /// of the same names.
+    /// Negative indices, which count from the end in JS, are not supported.
+    fn make_bytes_builtin(&self, builtin: IRBuiltin, args: &[IRExpr]) -> UResult {
+        let location = no_span();
+        let visit_arg = |arg: &IRExpr, is_bytes: bool| {
+            let ty = self.type_of(arg);
+            let is_ok = match ty {
+                IRTy::ByteArrayTy => is_bytes,
+                IRTy::UnknownTy | IRTy::Float64Ty | IRTy::BigIntTy => !is_bytes,
+                _ => false,
+            };
+            if !is_ok {
+                return Err(UError::BuiltinArgumentType { builtin, ty });
+            }
+            if !is_bytes && is_negative(arg) {
+                return Err(UError::NegativeIndex(builtin));
+            }
+
+            self.visit_integer(arg)
+        };
+
+        // The receiver, whose type tells whether this is the method of byte arrays at all
+        let ty = self.type_of(&args[0]);
+        if ty != IRTy::ByteArrayTy {
+            return Err(UError::MethodNotSupported {
+                method: builtin,
+                ty,
+            });
+        }
+
+        let bytes = visit_arg(&args[0], true)?;
+        let result = match (builtin, &args[1..]) {
+            (IRBuiltin::Concat, [other]) => {
+                let other = visit_arg(other, true)?;
+                self.builtin_call("append_bytearray", vec![bytes, other])
+            }
+            // Slicing past the end stops at the end
+            (IRBuiltin::Slice, [start]) => {
+                let start = visit_arg(start, false)?;
+                let length = self.builtin_call("length_of_bytearray", vec![bytes.clone()]);
+                self.builtin_call("slice_bytearray", vec![start, length, bytes])
+            }
+            (IRBuiltin::Slice, [start, end]) => {
+                let start = visit_arg(start, false)?;
+                let end = visit_arg(end, false)?;
+                let length = UntypedExpr::BinOp {
+                    location,
+                    name: BinOp::SubInt,
+                    left: Box::new(end),
+                    right: Box::new(start.clone()),
+                };
+                self.builtin_call("slice_bytearray", vec![start, length, bytes])
+            }
+            _ => return Err(UError::BuiltinArity(builtin)),
+        };
+
+        Ok(result.into())
+    }
+
+    /// `` `owner: ${name}` `` is `builtin.append_string("owner: ", name)`, with integers
+    /// converted by a prelude function and booleans by an `if`
+    fn visit_template(&self, template: &IRTemplate) -> UResult {
+        let location = no_span();
+        let string = |value: &str| UntypedExpr::String {
+            location,
+            value: value.to_string(),
+        };
+
+        let mut parts = Vec::with_capacity(template.quasis.len() + template.exprs.len());
+        for (i, quasi) in template.quasis.iter().enumerate() {
+            if !quasi.is_empty() {
+                parts.push(string(quasi));
+            }
+
+            let expr = match template.exprs.get(i) {
+                Some(expr) => expr,
+                None => continue,
+            };
+            let value = self.visit_expr(expr)?.to_expr_result()?;
+            let part = match self.type_of(expr) {
+                IRTy::StringTy | IRTy::UnknownTy => value,
+                IRTy::BigIntTy => self.prelude_call(PreludeFn::IntToString, vec![value]),
+                // Fixed-point numbers would show their scaled representation
+                IRTy::Float64Ty
+                    if !matches!(self.options.numbers, NumericSemantics::FixedPoint { .. }) =>
+                {
+                    self.prelude_call(PreludeFn::IntToString, vec![value])
+                }
+                IRTy::BooleanTy => UntypedExpr::If {
+                    location,
+                    branches: vec1![IfBranch {
+                        condition: value,
+                        body: string("true"),
+                        location,
+                    }],
+                    final_else: Box::new(string("false")),
+                },
+                ty => return Err(UError::TemplateArgumentType(ty)),
+            };
+            parts.push(part);
+        }
+
+        let mut parts = parts.into_iter();
+        let result = match parts.next() {
+            None => string(""),
+            Some(first) => parts.fold(first, |acc, part| {
+                self.builtin_call("append_string", vec![acc, part])
+            }),
+        };
+
+        Ok(result.into())
+    }
+
+    fn visit_lambda(&self, lambda: &IRLambda) -> UResult {
+        // Captured variables need no special treatment, aiken functions are closures
+        let arguments = self.make_arguments(&received_params(&lambda.params, &lambda.arity));
+        let body =
+            self.make_function_body(&lambda.params, &lambda.arity, &lambda.fun_ty, &lambda.body)?;
+
+        let result = UntypedExpr::Fn {
+            location: no_span(),
+            is_capture: false,
+            arguments,
+            body: Box::new(body),
+            return_annotation: ir_ty_to_annotation(lambda.fun_ty.ret_ty.as_ref()),
+        };
+
+        Ok(result.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::js_to_ir::parse_script;
+
+    fn definitions(code: &str, options: CompilerOptions) -> Vec<UntypedDefinition> {
+        let ir = parse_script(code, options).unwrap();
+        let builder = ModuleBuilderFromIR::with_options(options);
+
+        builder.visit_ir(&ir).unwrap().to_script_result().unwrap()
+    }
+
+    fn build(code: &str, traces: Traces) -> String {
+        let options = CompilerOptions {
+            traces,
+            ..Default::default()
+        };
+
+        format!("{:?}", definitions(code, options))
+    }
+
+    fn function<'a>(defs: &'a [UntypedDefinition], name: &str) -> &'a Function<(), UntypedExpr> {
+        defs.iter()
+            .find_map(|def| match def {
+                UntypedDefinition::Fn(function) if function.name == name => Some(function),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("no function {} in {:?}", name, defs))
+    }
+
+    fn arg_names(function: &Function<(), UntypedExpr>) -> Vec<&str> {
+        function
+            .arguments
+            .iter()
+            .map(|arg| match &arg.arg_name {
+                ArgName::Named { name, .. } => name.as_str(),
+                arg_name => panic!("unexpected argument {:?}", arg_name),
+            })
+            .collect()
+    }
+
+    /// The value of `expr`, the last expression of a sequence
+    fn result_of(expr: &UntypedExpr) -> &UntypedExpr {
+        match expr {
+            UntypedExpr::Sequence { expressions, .. } => result_of(expressions.last().unwrap()),
+            expr => expr,
+        }
+    }
+
+    #[test]
+    fn only_byte_arrays_have_slice_and_concat() {
+        let code = "function join(xs: bigint[], ys: bigint[]): bigint[] {
+            return xs.concat(ys);
+        }";
+        let ir = parse_script(code, CompilerOptions::default()).unwrap();
+        let result = ModuleBuilderFromIR::default().visit_ir(&ir);
+
+        assert!(matches!(
+            result,
+            Err(UError::MethodNotSupported {
+                method: IRBuiltin::Concat,
+                ty: IRTy::ListTy(_),
+            })
+        ));
+    }
+
+    #[test]
+    fn nullish_coalescing_is_a_when_and_not_an_operator() {
+        assert!(ir_bin_op_to_bin_op(IRBinOp::NullishCoalescing).is_none());
+
+        let defs = definitions(
+            "function or_zero(x: bigint | undefined): bigint {
+                return x ?? 0n;
+            }",
+            CompilerOptions::default(),
+        );
+
+        let body = result_of(&function(&defs, "or_zero").body);
+        assert!(matches!(body, UntypedExpr::When { .. }), "{:?}", body);
+    }
+
+    #[test]
+    fn narrowed_fields_do_not_shadow_the_variables_of_the_branch() {
+        let defs = definitions(
+            r#"
+            type Action = { kind: "claim" } | { kind: "cancel"; by: string };
+
+            function who(action: Action, action_by: string): string {
+                switch (action.kind) {
+                    case "claim":
+                        return action_by;
+                    case "cancel":
+                        return action.by + action_by;
+                }
+            }
+            "#,
+            CompilerOptions::default(),
+        );
+
+        let clauses = match result_of(&function(&defs, "who").body) {
+            UntypedExpr::When { clauses, .. } => clauses,
+            body => panic!("unexpected body {:?}", body),
+        };
+        let by = match &clauses[1].pattern[0] {
+            UntypedPattern::Constructor { arguments, .. } => match &arguments[..] {
+                [CallArg {
+                    value: UntypedPattern::Var { name, .. },
+                    ..
+                }] => name,
+                arguments => panic!("unexpected arguments {:?}", arguments),
+            },
+            pattern => panic!("unexpected pattern {:?}", pattern),
+        };
+        assert_ne!(by, "action_by");
+    }
+
+    #[test]
+    fn loop_helpers_are_not_named_after_user_functions() {
+        let defs = definitions(
+            "function main_loop_0(n: bigint): bigint {
+                return n;
+            }
+
+            function main(n: bigint): bigint {
+                let i = 0n;
+                while (i < n) {
+                    i = i + 1n;
+                }
+                return main_loop_0(i);
+            }",
+            CompilerOptions::default(),
+        );
+
+        let named = |name: &str| {
+            defs.iter()
+                .filter(|def| matches!(def, UntypedDefinition::Fn(f) if f.name == name))
+                .count()
+        };
+        assert_eq!(named("main_loop_0"), 1);
+        assert!(function(&defs, "main_loop_0").public);
+        assert!(!function(&defs, "main_loop_1").public);
+    }
+
+    #[test]
+    fn the_list_of_a_loop_does_not_shadow_the_variables_of_its_body() {
+        let defs = definitions(
+            "function sum(xs: bigint[], x_list: bigint, x_rest: bigint): bigint {
+                let total = 0n;
+                for (const x of xs) {
+                    total = total + x + x_list + x_rest;
+                }
+                return total;
+            }",
+            CompilerOptions::default(),
+        );
+        let helper = function(&defs, "sum_loop_0");
+
+        let args = arg_names(helper);
+        assert!(
+            args.contains(&"x_list") && args.contains(&"x_rest"),
+            "{:?}",
+            args
+        );
+        let list = args[0];
+        assert!(list != "x_list" && list != "x_rest", "{:?}", args);
+
+        match &helper.body {
+            UntypedExpr::When {
+                subjects, clauses, ..
+            } => {
+                assert!(
+                    matches!(subjects.as_slice(), [UntypedExpr::Var { name, .. }] if name == list)
+                );
+                match &clauses[1].pattern[0] {
+                    UntypedPattern::List {
+                        tail: Some(tail), ..
+                    } => {
+                        assert!(matches!(tail.as_ref(), UntypedPattern::Var { name, .. }
+                            if !args.contains(&name.as_str())));
+                    }
+                    pattern => panic!("unexpected pattern {:?}", pattern),
+                }
+            }
+            body => panic!("unexpected body {:?}", body),
+        }
+    }
+
+    #[test]
+    fn increments_are_bigints_when_numbers_are_not_allowed() {
+        let options = CompilerOptions {
+            numbers: NumericSemantics::BigIntOnly,
+            ..Default::default()
+        };
+        let defs = definitions(
+            "function count(n: bigint): bigint {
+                let total = 0n;
+                for (let i = 0n; i < n; i++) {
+                    total++;
+                }
+                return total;
+            }",
+            options,
+        );
+
+        function(&defs, "count");
+        function(&defs, "count_loop_0");
+    }
+
+    #[test]
+    fn stripped_traces_build_the_same_script_as_no_logging() {
+        let logging = r#"
+            function log(x: bigint): void {
+                console.log("x is", x);
+            }
+
+            function next(x: bigint): bigint {
+                console.log("next of", x);
+                let y = x + 1n;
+                if (y > 2n) {
+                    console.log("big");
+                }
+                log(y);
+                return y;
+            }
+        "#;
+        let no_logging = r#"
+            function log(x: bigint): void {
+            }
+
+            function next(x: bigint): bigint {
+                let y = x + 1n;
+                log(y);
+                return y;
+            }
+        "#;
+
+        assert_eq!(
+            build(logging, Traces::Strip),
+            build(no_logging, Traces::Strip)
+        );
+        assert_ne!(
+            build(logging, Traces::Keep),
+            build(no_logging, Traces::Keep)
+        );
+    }
+
+    /// The name of the type variable `annotation`
+    fn type_var(annotation: &Annotation) -> &str {
+        match annotation {
+            Annotation::Var { name, .. } => name,
+            annotation => panic!("not a type variable: {:?}", annotation),
+        }
+    }
+
+    /// The type variables of the tuple `annotation`
+    fn tuple_vars(annotation: &Annotation) -> Vec<&str> {
+        match annotation {
+            Annotation::Tuple { elems, .. } => elems.iter().map(type_var).collect(),
+            annotation => panic!("not a tuple: {:?}", annotation),
+        }
+    }
+
+    #[test]
+    fn generic_types_lower_to_aiken_generics() {
+        let defs = definitions(
+            r#"
+            interface Box<T> {
+                value: T;
+            }
+
+            function swap<K, k>(box: Box<[K, k]>): [k, K] {
+                return [box.value[1], box.value[0]];
+            }
+            "#,
+            CompilerOptions::default(),
+        );
+
+        let datatype = defs
+            .iter()
+            .find_map(|def| match def {
+                UntypedDefinition::DataType(datatype) if datatype.name == "Box" => Some(datatype),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(datatype.parameters, vec!["t".to_string()]);
+        let field = &datatype.constructors[0].arguments[0];
+        assert_eq!(type_var(&field.annotation), "t");
+
+        // `K` and `k` are both lowercase in aiken, but must stay two variables
+        let swap = function(&defs, "swap");
+        let (k, k_) = match swap.arguments[0].annotation.as_ref() {
+            Some(Annotation::Constructor {
+                name, arguments, ..
+            }) if name == "Box" => match tuple_vars(&arguments[0])[..] {
+                [k, k_] => (k, k_),
+                ref vars => panic!("unexpected type arguments {:?}", vars),
+            },
+            annotation => panic!("unexpected annotation {:?}", annotation),
+        };
+        assert_ne!(k, k_);
+        assert_eq!(
+            tuple_vars(swap.return_annotation.as_ref().unwrap()),
+            vec![k_, k]
+        );
+    }
+
+    #[test]
+    fn a_function_that_does_nothing_is_the_unit_value() {
+        let options = CompilerOptions {
+            traces: Traces::Strip,
+            ..Default::default()
+        };
+        let defs = definitions("function log(x: bigint): void {}", options);
+
+        let body = &function(&defs, "log").body;
+        assert!(
+            matches!(body, UntypedExpr::Var { name, .. } if name == UNIT),
+            "{:?}",
+            body
+        );
     }
 }
@@ -1,19 +1,30 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct IRFunTy {
+    /// The type parameters, e.g. `T` in `function first<T>(xs: T[]): T`, named as the aiken
+    /// type variables they become
+    pub type_params: Vec<String>,
     pub param_tys: Vec<IRTy>,
     pub ret_ty: Box<IRTy>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum IRTy {
-    UnitTy,    // ts: void
-    BooleanTy, // ts: boolean
-    Float64Ty, // ts: number
-    BigIntTy,  // ts: bigint
-    StringTy,  // ts: string
+    UnitTy,      // ts: void
+    BooleanTy,   // ts: boolean
+    Float64Ty,   // ts: number
+    BigIntTy,    // ts: bigint
+    StringTy,    // ts: string
+    ByteArrayTy, // ts: Uint8Array, or its alias ByteArray
     FunTy(Box<IRFunTy>),
+    ListTy(Box<IRTy>),          // ts: T[] or Array<T>
+    TupleTy(Vec<IRTy>),         // ts: [A, B]
+    OptionTy(Box<IRTy>),        // ts: T | undefined, or the type of an optional property
+    NamedTy(String, Vec<IRTy>), // ts: a reference to an `interface` or `type`, e.g. `Pair<A, B>`
+    VarTy(String),              // ts: a type parameter, e.g. `T` in `first<T>(xs: T[]): T`
 
     UnknownTy, // no type declared
 }
@@ -21,3 +32,138 @@ pub enum IRTy {
 pub trait TypeOf {
     fn type_of(&self) -> IRTy;
 }
+
+impl IRFunTy {
+    /// The return type of a call with arguments of types `arg_tys`, where the type parameters
+    /// are replaced with the types of the arguments they stand for, or unknown.
+    pub fn instantiate_ret_ty(&self, arg_tys: &[IRTy]) -> IRTy {
+        let mut bindings = HashMap::new();
+        for (param_ty, arg_ty) in self.param_tys.iter().zip(arg_tys) {
+            param_ty.bind_vars(arg_ty, &self.type_params, &mut bindings);
+        }
+
+        self.ret_ty.substitute(&self.type_params, &bindings)
+    }
+}
+
+impl IRTy {
+    /// `T | undefined`, which is `T` itself if it is already optional
+    pub fn optional(self) -> IRTy {
+        match self {
+            IRTy::OptionTy(_) => self,
+            ty => IRTy::OptionTy(Box::new(ty)),
+        }
+    }
+
+    /// `self` with the type parameters in `params` replaced with the types in `args`,
+    /// or unknown if there are fewer arguments than parameters
+    pub fn instantiate(&self, params: &[String], args: &[IRTy]) -> IRTy {
+        let bindings = params.iter().cloned().zip(args.iter().cloned()).collect();
+        self.substitute(params, &bindings)
+    }
+
+    /// Binds the type parameters in `params` that occur in `self` to the matching parts of `ty`.
+    /// The first binding wins.
+    fn bind_vars(&self, ty: &IRTy, params: &[String], bindings: &mut HashMap<String, IRTy>) {
+        match (self, ty) {
+            (_, IRTy::UnknownTy) => {}
+            (IRTy::VarTy(name), ty) if params.contains(name) => {
+                bindings.entry(name.clone()).or_insert_with(|| ty.clone());
+            }
+            (IRTy::ListTy(elem_ty), IRTy::ListTy(ty)) => elem_ty.bind_vars(ty, params, bindings),
+            (IRTy::OptionTy(elem_ty), IRTy::OptionTy(ty)) => {
+                elem_ty.bind_vars(ty, params, bindings)
+            }
+            // A value passed where an option is expected becomes `Some(value)`
+            (IRTy::OptionTy(elem_ty), ty) => elem_ty.bind_vars(ty, params, bindings),
+            (IRTy::TupleTy(elem_tys), IRTy::TupleTy(tys)) => {
+                for (elem_ty, ty) in elem_tys.iter().zip(tys) {
+                    elem_ty.bind_vars(ty, params, bindings);
+                }
+            }
+            (IRTy::NamedTy(name, type_args), IRTy::NamedTy(other, tys)) if name == other => {
+                for (type_arg, ty) in type_args.iter().zip(tys) {
+                    type_arg.bind_vars(ty, params, bindings);
+                }
+            }
+            (IRTy::FunTy(fun_ty), IRTy::FunTy(ty)) => {
+                for (param_ty, ty) in fun_ty.param_tys.iter().zip(&ty.param_tys) {
+                    param_ty.bind_vars(ty, params, bindings);
+                }
+                fun_ty.ret_ty.bind_vars(&ty.ret_ty, params, bindings);
+            }
+            _ => {}
+        }
+    }
+
+    /// `self` with the type parameters in `params` replaced with their bindings, or unknown
+    fn substitute(&self, params: &[String], bindings: &HashMap<String, IRTy>) -> IRTy {
+        let substitute = |ty: &IRTy| Box::new(ty.substitute(params, bindings));
+
+        match self {
+            IRTy::VarTy(name) if params.contains(name) => {
+                bindings.get(name).cloned().unwrap_or(IRTy::UnknownTy)
+            }
+            IRTy::ListTy(elem_ty) => IRTy::ListTy(substitute(elem_ty)),
+            IRTy::OptionTy(elem_ty) => IRTy::OptionTy(substitute(elem_ty)),
+            IRTy::TupleTy(elem_tys) => IRTy::TupleTy(
+                elem_tys
+                    .iter()
+                    .map(|ty| ty.substitute(params, bindings))
+                    .collect(),
+            ),
+            IRTy::NamedTy(name, type_args) => IRTy::NamedTy(
+                name.clone(),
+                type_args
+                    .iter()
+                    .map(|ty| ty.substitute(params, bindings))
+                    .collect(),
+            ),
+            IRTy::FunTy(fun_ty) => IRTy::FunTy(Box::new(IRFunTy {
+                type_params: fun_ty.type_params.clone(),
+                param_tys: fun_ty
+                    .param_tys
+                    .iter()
+                    .map(|ty| ty.substitute(params, bindings))
+                    .collect(),
+                ret_ty: substitute(&fun_ty.ret_ty),
+            })),
+            ty => ty.clone(),
+        }
+    }
+}
+
+/// The types of the variables in scope, innermost scope last
+#[derive(Default, Debug)]
+pub struct TypeEnv {
+    scopes: Vec<HashMap<String, IRTy>>,
+}
+
+impl TypeEnv {
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub fn bind(&mut self, name: String, ty: IRTy) {
+        if self.scopes.is_empty() {
+            self.push_scope();
+        }
+
+        // Safe, we just made sure there is a scope
+        self.scopes.last_mut().unwrap().insert(name, ty);
+    }
+
+    /// The type of a variable, which is [IRTy::UnknownTy] for variables we know nothing about.
+    pub fn lookup(&self, name: &str) -> IRTy {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+            .unwrap_or(IRTy::UnknownTy)
+    }
+}
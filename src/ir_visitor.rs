@@ -10,11 +10,22 @@ pub trait IRVisitor<VResult> {
     fn visit_ir(&self, ir: &IR) -> VResult {
         match ir {
             IR::Script(script) => self.visit_script(script),
+            IR::Import(import) => self.visit_import(import),
             IR::FunDef(fundef) => self.visit_fundef(fundef),
             IR::VarDef(vardef) => self.visit_vardef(vardef),
+            IR::PatDef(patdef) => self.visit_patdef(patdef),
+            IR::TypeDef(typedef) => self.visit_typedef(typedef),
             IR::ReturnStmt(retstmt) => self.visit_retstmt(retstmt),
+            IR::ThrowStmt(throwstmt) => self.visit_throwstmt(throwstmt),
+            IR::TraceStmt(tracestmt) => self.visit_tracestmt(tracestmt),
             IR::BlockStmt(blockstmt) => self.visit_blockstmt(blockstmt),
             IR::IfStmt(ifstmt) => self.visit_ifstmt(ifstmt),
+            IR::SwitchStmt(switchstmt) => self.visit_switchstmt(switchstmt),
+            IR::AssignStmt(assignstmt) => self.visit_assignstmt(assignstmt),
+            IR::ForOfStmt(forofstmt) => self.visit_forofstmt(forofstmt),
+            IR::WhileStmt(whilestmt) => self.visit_whilestmt(whilestmt),
+            IR::JoinStmt(joinstmt) => self.visit_joinstmt(joinstmt),
+            IR::ContinuationDef(contdef) => self.visit_continuationdef(contdef),
             IR::ExprStmt(exprstmt) => self.visit_exprstmt(exprstmt),
             IR::Expr(expr) => self.visit_expr(expr),
             IR::Paren(expr) => self.visit_expr(expr),
@@ -22,24 +33,55 @@ pub trait IRVisitor<VResult> {
     }
 
     fn visit_script(&self, script: &IRScript) -> VResult;
+    fn visit_import(&self, import: &IRImport) -> VResult;
     fn visit_fundef(&self, fundef: &IRFunDef) -> VResult;
     fn visit_vardef(&self, vardef: &IRVarDef) -> VResult;
+    fn visit_patdef(&self, patdef: &IRPatDef) -> VResult;
+    fn visit_typedef(&self, typedef: &IRTypeDef) -> VResult;
     fn visit_retstmt(&self, retstmt: &IRReturnStmt) -> VResult;
+    fn visit_throwstmt(&self, throwstmt: &IRThrowStmt) -> VResult;
+    fn visit_tracestmt(&self, tracestmt: &IRTraceStmt) -> VResult;
     fn visit_blockstmt(&self, blockstmt: &IRBlockStmt) -> VResult;
     fn visit_ifstmt(&self, ifstmt: &IRIfStmt) -> VResult;
+    fn visit_switchstmt(&self, switchstmt: &IRSwitchStmt) -> VResult;
+    fn visit_assignstmt(&self, assignstmt: &IRAssignStmt) -> VResult;
+    fn visit_forofstmt(&self, forofstmt: &IRForOfStmt) -> VResult;
+    fn visit_whilestmt(&self, whilestmt: &IRWhileStmt) -> VResult;
+    fn visit_joinstmt(&self, joinstmt: &IRJoinStmt) -> VResult;
+    fn visit_continuationdef(&self, contdef: &IRContinuationDef) -> VResult;
     fn visit_exprstmt(&self, exprstmt: &IRExprStmt) -> VResult;
 
     fn visit_expr(&self, expr: &IRExpr) -> VResult {
         match expr {
             IRExpr::Literal(literal) => self.visit_literal(literal),
             IRExpr::Identifier(ident) => self.visit_ident(ident), // todo this needs a symbol table?
+            IRExpr::Unary(unary_op) => self.visit_unary_op(unary_op),
             IRExpr::Binary(binary_op) => self.visit_binary_op(binary_op),
+            IRExpr::Cond(cond) => self.visit_cond(cond),
+            IRExpr::Object(object) => self.visit_object(object),
+            IRExpr::Member(member) => self.visit_member(member),
+            IRExpr::OptMember(member) => self.visit_opt_member(member),
+            IRExpr::Array(array) => self.visit_array(array),
+            IRExpr::Index(index) => self.visit_index(index),
             IRExpr::Apply(apply) => self.visit_apply(apply),
+            IRExpr::Builtin(builtin_call) => self.visit_builtin_call(builtin_call),
+            IRExpr::Template(template) => self.visit_template(template),
+            IRExpr::Lambda(lambda) => self.visit_lambda(lambda),
         }
     }
 
     fn visit_literal(&self, literal: &IRLiteral) -> VResult;
     fn visit_ident(&self, ident: &IRIdent) -> VResult;
+    fn visit_unary_op(&self, unary_op: &IRUnaryExpr) -> VResult;
     fn visit_binary_op(&self, binary_op: &IRBinaryExpr) -> VResult;
+    fn visit_cond(&self, cond: &IRCondExpr) -> VResult;
+    fn visit_object(&self, object: &IRObjectLit) -> VResult;
+    fn visit_member(&self, member: &IRMember) -> VResult;
+    fn visit_opt_member(&self, member: &IRMember) -> VResult;
+    fn visit_array(&self, array: &IRArrayLit) -> VResult;
+    fn visit_index(&self, index: &IRIndex) -> VResult;
     fn visit_apply(&self, apply: &IRApply) -> VResult;
+    fn visit_builtin_call(&self, builtin_call: &IRBuiltinCall) -> VResult;
+    fn visit_template(&self, template: &IRTemplate) -> VResult;
+    fn visit_lambda(&self, lambda: &IRLambda) -> VResult;
 }
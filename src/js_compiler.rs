@@ -1,18 +1,18 @@
 use aiken_lang::ast::{ModuleKind, TypedFunction, UntypedModule};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
 
 use crate::copy_aiken_project_lib::Project;
 use aiken_project::config::Config;
 use aiken_project::module::{CheckedModules, ParsedModule, ParsedModules};
 use aiken_project::script::Script;
 use aiken_project::telemetry::EventListener;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::rc::Rc;
 
 use serde::{Deserialize, Serialize};
 
-use ir_to_unode::UResult;
 use swc_core::common::comments::SingleThreadedComments;
 use swc_core::common::input::StringInput;
 use swc_core::common::sync::Lrc;
@@ -23,12 +23,12 @@ use swc_core::ecma::parser::error::Error as SWCError;
 use swc_core::ecma::parser::lexer::Lexer;
 use swc_core::ecma::parser::{PResult, Parser, Syntax, TsConfig};
 
-use crate::ir::IR;
+use crate::ir::{IRImport, IR};
 use crate::ir_to_unode::ModuleBuilderFromIR;
 use crate::ir_visitor::IRVisitor;
+use crate::js_compiler;
 use crate::js_to_ir::JsToIR;
-use crate::program::{Error, Language, Source};
-use crate::{ir_to_unode, js_compiler};
+use crate::program::{CompilerOptions, Error, Language, Source};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JsError {
@@ -143,6 +143,7 @@ pub type JsSource = Source<JsSyntaxEsVersion>;
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Javascript {
     source: JsSource,
+    options: CompilerOptions,
 }
 
 impl From<aiken_project::error::Error> for Error {
@@ -153,9 +154,112 @@ impl From<aiken_project::error::Error> for Error {
 
 pub type Validators = Vec<(PathBuf, String, TypedFunction)>;
 
+/// The extensions we try, in order, when resolving an `import` specifier without one.
+const MODULE_EXTENSIONS: [&str; 2] = ["ts", "js"];
+
+/// One source file of the program, lowered to our IR.
+#[derive(Debug)]
+pub struct JsModule {
+    /// The module name, as seen by aiken
+    pub name: String,
+    /// The entry point is a validator module, everything it imports is a library module.
+    pub kind: ModuleKind,
+    pub source: JsSource,
+    pub ir: IR,
+}
+
+/// Removes the `.` and `..` components of `path`, without looking at the file system,
+/// so that a file reached through different relative paths gets a single name.
+/// A `..` that cannot be resolved, at the start of a relative path, is kept.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Finds the file an `import` specifier refers to, relative to the importing file.
+/// The path is normalised, see [normalize_path], and must stay within `root`.
+fn resolve_import(root: &Path, importer: &Path, src: &str) -> Result<PathBuf, Error> {
+    let dir = importer.parent().unwrap_or_else(|| Path::new(""));
+    let path = normalize_path(&dir.join(src));
+
+    let escapes_root = match path.strip_prefix(root) {
+        Ok(rest) => rest.components().any(|c| c == Component::ParentDir),
+        Err(_) => true,
+    };
+    if escapes_root {
+        return Err(Error::ImportOutsideRoot {
+            importer: importer.to_path_buf(),
+            src: src.to_string(),
+        });
+    }
+
+    if path.extension().is_some() && path.is_file() {
+        return Ok(path);
+    }
+
+    MODULE_EXTENSIONS
+        .iter()
+        .map(|ext| path.with_extension(ext))
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| Error::ModuleNotFound {
+            importer: importer.to_path_buf(),
+            src: src.to_string(),
+        })
+}
+
+/// Computes the aiken module name of `path`, which is its path relative to `root`
+/// without the extension, e.g. `lib/helpers.ts` becomes `lib/helpers`.
+fn module_name(root: &Path, path: &Path) -> Result<String, Error> {
+    let mut module_path = path
+        .strip_prefix(root)
+        .map_err(|_| Error::BadFilename(path.to_path_buf()))?
+        .to_path_buf();
+    module_path.set_extension("");
+
+    let name = module_path
+        .to_str()
+        .ok_or_else(|| Error::BadFilename(path.to_path_buf()))?;
+
+    // normalise windows paths
+    Ok(name.replace('\\', "/"))
+}
+
+/// Collects the `import`s of a module, so that they can be resolved.
+fn imports_of_mut(ir: &mut IR) -> Vec<&mut IRImport> {
+    match ir {
+        IR::Script(script) => script
+            .body
+            .iter_mut()
+            .filter_map(|ir| match ir {
+                IR::Import(import) => Some(import),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
 impl Javascript {
     fn new(source: JsSource) -> Self {
-        Javascript { source }
+        Javascript::with_options(source, CompilerOptions::default())
+    }
+
+    fn with_options(source: JsSource, options: CompilerOptions) -> Self {
+        Javascript { source, options }
     }
 
     fn name(&self) -> String {
@@ -168,7 +272,7 @@ impl Javascript {
     }
 
     fn transform_swc_to_ir(&self, program: &SWCProgram) -> Result<IR, Error> {
-        let ir = JsToIR::default().visit_program(program)?;
+        let ir = JsToIR::new(self.options).visit_program(program)?;
         Ok(ir)
     }
 
@@ -177,38 +281,98 @@ impl Javascript {
         self.transform_swc_to_ir(program.as_ref())
     }
 
-    fn transform_ir_to_aiken_untyped(&self, ir: &IR) -> Result<UntypedModule, Error> {
-        let script_name = self
-            .source
-            .script_path
-            .to_str()
-            .ok_or_else(|| Error::BadFilename(self.source.script_path.to_path_buf()))?;
-        let builder = <ModuleBuilderFromIR as IRVisitor<UResult>>::new();
-        let unode = builder.visit_ir(ir)?;
-        let module = unode.make_untyped_module(script_name.to_string())?;
-
-        Ok(module)
+    /// Parses the entry point and, transitively, every module it imports.
+    /// Imports are resolved relative to the importing file and each file is parsed only once.
+    /// The entry point comes first in the result.
+    fn parse_js_to_ir_modules(&self) -> Result<Vec<JsModule>, Error> {
+        let script_path = normalize_path(&self.source.script_path);
+        let root = script_path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .to_path_buf();
+
+        // Named like the modules it imports, rather than after its file
+        let entry_name = module_name(&root, &script_path)?;
+
+        let mut modules = Vec::new();
+        let mut seen = HashSet::from([script_path]);
+        let mut pending =
+            VecDeque::from([(entry_name, ModuleKind::Validator, self.source.clone())]);
+
+        while let Some((name, kind, source)) = pending.pop_front() {
+            let ParsedJs { program, .. } = parse_js_source(&source)?;
+            let mut ir = self.transform_swc_to_ir(program.as_ref())?;
+
+            for import in imports_of_mut(&mut ir) {
+                let path = resolve_import(&root, &source.script_path, &import.src)?;
+                let import_name = module_name(&root, &path)?;
+                import.module = Some(import_name.clone());
+
+                if seen.insert(path.clone()) {
+                    let code =
+                        fs::read_to_string(&path).map_err(|_| Error::BadFilename(path.clone()))?;
+                    let import_source = Source {
+                        lang: source.lang,
+                        extra: source.extra,
+                        script_name: import_name.clone(),
+                        script_path: path,
+                        code,
+                    };
+
+                    pending.push_back((import_name, ModuleKind::Lib, import_source));
+                }
+            }
+
+            modules.push(JsModule {
+                name,
+                kind,
+                source,
+                ir,
+            });
+        }
+
+        Ok(modules)
+    }
+
+    /// Transforms `module`, which can use the types and functions of the other `modules`.
+    fn transform_ir_to_aiken_untyped(
+        &self,
+        module: &JsModule,
+        modules: &[JsModule],
+    ) -> Result<UntypedModule, Error> {
+        let builder = ModuleBuilderFromIR::with_options(self.options);
+        for other in modules.iter().filter(|other| other.name != module.name) {
+            builder.bind_module(&other.name, &other.ir);
+        }
+        let unode = builder.visit_ir(&module.ir)?;
+        let umod = unode.make_untyped_module(module.name.clone(), module.kind)?;
+
+        Ok(umod)
     }
 
     /// Note: the basic logic is from Aiken's compilation pipeline, adjusted for our use-case.
     /// See [aiken_project::Project] and [aiken_project_copy::Project]
     fn transform_aiken_untyped_to_typed<T: EventListener>(
         &self,
-        umod: &UntypedModule,
+        umods: Vec<(&JsModule, UntypedModule)>,
         event_listener: T,
     ) -> Result<(RefCell<Project<T>>, Validators, CheckedModules), Error> {
         let name = &self.source.script_name;
-        let pmodule = ParsedModule {
-            path: self.source.script_path.clone(),
-            name: name.clone(),
-            code: "".to_string(),
-            kind: ModuleKind::Validator,
-            package: "".to_string(),
-            ast: umod.clone(),
-            extra: Default::default(),
-        };
 
-        let umodmap = HashMap::from([(name.clone(), pmodule)]);
+        let mut umodmap = HashMap::with_capacity(umods.len());
+        for (module, umod) in umods {
+            let pmodule = ParsedModule {
+                path: module.source.script_path.clone(),
+                name: module.name.clone(),
+                code: "".to_string(),
+                kind: module.kind,
+                package: "".to_string(),
+                ast: umod,
+                extra: Default::default(),
+            };
+
+            umodmap.insert(module.name.clone(), pmodule);
+        }
         let parsed_modules: ParsedModules = umodmap.into();
 
         let config = Config {
@@ -242,18 +406,20 @@ impl Javascript {
     }
 
     pub fn end_to_end<T: EventListener>(&self, event_listener: T) -> Result<Vec<Script>, Error> {
-        // 1. Parse Javascript/Typescript source to `swc` AST.
-        let ParsedJs { program, comments } = self.parse_js_to_swc()?;
-
+        // 1. Parse Javascript/Typescript sources to `swc` AST, following the imports.
         // 2. Transform `swc` AST to `jutus` IR.
-        let ir = self.transform_swc_to_ir(program.as_ref())?;
+        let modules = self.parse_js_to_ir_modules()?;
 
         // 3. Transform `jutus` IR to `aiken` untyped trees (`UntypedModule`).
-        let untyped_module = self.transform_ir_to_aiken_untyped(&ir)?;
+        let mut untyped_modules = Vec::with_capacity(modules.len());
+        for module in &modules {
+            let untyped_module = self.transform_ir_to_aiken_untyped(module, &modules)?;
+            untyped_modules.push((module, untyped_module));
+        }
 
         // 4. Transform `aiken` untyped to typed trees (`TypedModule`).
         let (project, validators, checked_modules) =
-            self.transform_aiken_untyped_to_typed(&untyped_module, event_listener)?;
+            self.transform_aiken_untyped_to_typed(untyped_modules, event_listener)?;
 
         // 5. Generate code
         let mut project = project.borrow_mut();
@@ -263,6 +429,15 @@ impl Javascript {
 
 /// Just a helper for writing main() functions in examples/
 pub fn parser_main_helper(code: &str, fname: &Path) -> Result<(), Error> {
+    parser_main_helper_with_options(code, fname, CompilerOptions::default())
+}
+
+/// [parser_main_helper] with non-default [CompilerOptions]
+pub fn parser_main_helper_with_options(
+    code: &str,
+    fname: &Path,
+    options: CompilerOptions,
+) -> Result<(), Error> {
     let jssynesv = JsSyntaxEsVersion::default();
     let ParsedJs { program, comments } = js_compiler::parse_js(code, fname, jssynesv)?;
 
@@ -288,12 +463,14 @@ pub fn parser_main_helper(code: &str, fname: &Path) -> Result<(), Error> {
     println!();
     println!("============================");
     println!("=== IR =====================");
-    let js = Javascript::new(source);
+    let js = Javascript::with_options(source, options);
     let name = js.name();
-    let ir = js.parse_js_to_ir()?;
+    let modules = js.parse_js_to_ir_modules()?;
 
-    let ir_pretty = serde_json::to_string_pretty(&ir).unwrap();
-    println!("{}", ir_pretty);
+    for module in &modules {
+        let ir_pretty = serde_json::to_string_pretty(&module.ir).unwrap();
+        println!("{}", ir_pretty);
+    }
 
     println!();
     println!("============================");
@@ -301,20 +478,24 @@ pub fn parser_main_helper(code: &str, fname: &Path) -> Result<(), Error> {
 
     println!();
     println!("============================");
-    let umod = js.transform_ir_to_aiken_untyped(&ir)?;
-    println!("UNTYPED {:?}", umod);
+    let mut umods = Vec::with_capacity(modules.len());
+    for module in &modules {
+        let umod = js.transform_ir_to_aiken_untyped(module, &modules)?;
+        println!("UNTYPED {:?}", umod);
+        umods.push((module, umod));
+    }
 
     println!();
     println!("============================");
     let listener = aiken::Terminal::default();
     let (project, validators, checked_modules) =
-        js.transform_aiken_untyped_to_typed(&umod, listener)?;
-    // Note Assuming an untyped module gives rise to a corresponding typed module
-    let tmod = &checked_modules.values().next().unwrap().ast;
-    // let tmod = tmod.clone();
-    // println!("TYPED {:?}", tmod);
-    for tdef in tmod.definitions.iter() {
-        println!("TDEF {:?}", tdef);
+        js.transform_aiken_untyped_to_typed(umods, listener)?;
+    for tmod in checked_modules.values() {
+        // let tmod = tmod.clone();
+        // println!("TYPED {:?}", tmod);
+        for tdef in tmod.ast.definitions.iter() {
+            println!("TDEF {:?}", tdef);
+        }
     }
 
     println!();
@@ -325,9 +506,12 @@ pub fn parser_main_helper(code: &str, fname: &Path) -> Result<(), Error> {
         println!("SCRIPT[{:?}] {:?}", index, script);
     }
 
-    let eval_infos = project.eval_scripts(scripts, None);
-    for (index, eval_info) in eval_infos.iter().enumerate() {
+    let eval_infos = project.eval_scripts_with_logs(scripts, None);
+    for (index, (eval_info, logs)) in eval_infos.iter().enumerate() {
         println!("EvalInfo[{:?}] {:?}", index, eval_info);
+        for log in logs {
+            println!("EvalInfo[{:?}] log: {}", index, log);
+        }
     }
 
     Ok(())
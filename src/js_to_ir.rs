@@ -1,43 +1,289 @@
 //! Visit the AST generated by `swc` and generate our IR
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+
+use num_bigint::BigInt;
 use serde::{Deserialize, Serialize};
 
 use swc_core::ecma::ast::{
-    BinExpr, BinaryOp, BlockStmt, Decl, Expr, ExprStmt, FnDecl, Ident, IfStmt, Lit, Param,
-    ParenExpr, ReturnStmt, Script, TsKeywordType, TsKeywordTypeKind, TsType, TsTypeAnn, VarDecl,
-    VarDeclKind,
+    ArrayLit, ArrayPat, ArrowExpr, AssignExpr, AssignOp, AssignPat, AssignPatProp, BinExpr,
+    BinaryOp, BlockStmt, BlockStmtOrExpr, BreakStmt, CallExpr, Callee, ComputedPropName, CondExpr,
+    Decl, Expr, ExprOrSpread, ExprStmt, FnDecl, FnExpr, ForOfStmt, ForStmt, Function, Ident,
+    IfStmt, ImportDecl, ImportSpecifier, KeyValuePatProp, KeyValueProp, Lit, MemberExpr,
+    MemberProp, Module, ModuleDecl, ModuleExportName, ModuleItem, NewExpr, ObjectLit, ObjectPat,
+    ObjectPatProp, OptChainBase, OptChainExpr, ParenExpr, PatOrExpr, Prop, PropName, PropOrSpread,
+    RestPat, ReturnStmt, Script, SwitchStmt, TaggedTpl, ThrowStmt, Tpl, TsEntityName,
+    TsFnOrConstructorType, TsFnParam, TsFnType, TsInterfaceDecl, TsKeywordType, TsKeywordTypeKind,
+    TsLit, TsLitType, TsType, TsTypeAliasDecl, TsTypeAnn, TsTypeElement, TsTypeParamDecl,
+    TsTypeRef, TsUnionOrIntersectionType, TsUnionType, UnaryExpr, UnaryOp, UpdateExpr, UpdateOp,
+    VarDecl, VarDeclKind, VarDeclOrExpr, VarDeclOrPat, WhileStmt,
 };
 use swc_core::ecma::ast::{BindingIdent, Pat, Program, Stmt};
 
 use crate::ir::{
-    IRBinOp, IRBinaryExpr, IRBlockStmt, IRExpr, IRExprStmt, IRFunDef, IRIdent, IRIfStmt, IRLiteral,
-    IRNameTy, IRReturnStmt, IRScript, IRVarDef, IR,
+    IRApply, IRArity, IRArrayLit, IRAssignStmt, IRBinOp, IRBinaryExpr, IRBlockStmt, IRBuiltin,
+    IRBuiltinCall, IRCondExpr, IRConstructor, IRExpr, IRExprStmt, IRForOfStmt, IRFunDef, IRIdent,
+    IRIfStmt, IRImport, IRImportSpecifier, IRIndex, IRLambda, IRLiteral, IRMember, IRNameTy,
+    IRObjectLit, IRPatDef, IRPattern, IRProp, IRPropPattern, IRReturnStmt, IRScript, IRSwitchCase,
+    IRSwitchStmt, IRTemplate, IRThrowStmt, IRTraceStmt, IRTypeDef, IRUnOp, IRUnaryExpr, IRVarDef,
+    IRWhileStmt, IR,
 };
+use crate::ir_free_vars::{fresh_name, names, FreeVars, Vars};
+use crate::ir_normalize::{may_return_value, normalize_body};
+use crate::ir_ssa::ssa_body;
 use crate::ir_type::{IRFunTy, IRTy};
-use crate::program::Error;
+use crate::ir_visitor::IRVisitor;
+use crate::program::{CompilerOptions, Error, NumericSemantics, Traces};
 
 #[derive(Default)]
-pub struct JsToIR;
+pub struct JsToIR {
+    options: CompilerOptions,
+    /// The type parameters of the enclosing generic functions or types, e.g. `T` in
+    /// `function first<T>(xs: T[]): T`, with the names of the aiken type variables they become
+    type_params: RefCell<Vec<(String, String)>>,
+    /// Whether the module defines or imports its own `assert`, which hides the one of
+    /// our prelude
+    own_assert: Cell<bool>,
+    /// With [Traces::Strip], the variables that only the stripped traces of the functions
+    /// being visited use, which an enclosing function or the module must still define
+    traced_vars: RefCell<Vars>,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum IRError {
     ModulesNotSupported,
+    ModuleDeclNotSupported,
+    ImportNotSupported(String),
     StatementNotSupported,
     ExpressionNotSupported(String),
+    CalleeNotSupported(String),
+    SpreadNotSupported,
     BinaryOpNotSupported,
+    /// The operator makes no sense on-chain, `reason` explains why.
+    UnaryOpNotSupported {
+        op: String,
+        reason: String,
+    },
+    /// Only `` hex`...` `` literals
+    TaggedTemplateNotSupported,
+    /// A `` hex`...` `` literal must have an even number of hex digits and no `${...}`
+    HexLiteralNotSupported(String),
+    /// The function of `Math` makes no sense on-chain, `reason` explains why.
+    MathNotSupported {
+        function: String,
+        reason: String,
+    },
     LiteralNotSupported,
+    /// `number` literals must be safe integers, `1.5` or `1e21` cannot be represented on-chain,
+    /// unless they fit the scale of [NumericSemantics::FixedPoint]
+    NumberNotAnInteger(String),
+    /// `number` is rejected with [NumericSemantics::BigIntOnly]
+    NumberNotAllowed(String),
     ParameterNotSupported,
+    /// Default values cannot refer to the parameters, whose own defaults may not have been
+    /// computed yet
+    DefaultParamNotSupported(String),
     DeclarationNotSupported,
-    VarDeclLenNotSupported,
     VarNotSupported,
     VarDeclNoInitializerNotSupported,
     TsTypeNotSupported,
+    /// Type parameters cannot have a constraint or a default, aiken has no bounded generics
+    TypeParamNotSupported(String),
+    TsTypeMemberNotSupported,
+    PropNotSupported,
+    UnionNotSupported(String),
+    UnionWithoutDiscriminant(String),
+    /// Two members of tagged unions, or a record, end up with the same aiken constructor,
+    /// see [constructor_name_of_tag]
+    ConstructorNameClash(String),
+    SwitchCaseNotSupported,
+    SwitchFallthroughNotSupported,
+    /// Only `x = value` and `x op= value` on a variable, as a statement
+    AssignmentNotSupported,
+    ArrayHoleNotSupported,
+    /// Only constant, non-negative indices, e.g. `pair[0]`
+    IndexNotSupported,
+    /// Only `for (const x of list)`
+    ForOfNotSupported,
+    /// Loops are rewritten to recursive functions, we cannot return from the enclosing function
+    ReturnInLoopNotSupported,
+    /// Only `throw new Error("message")`, since the message becomes the label of an aiken
+    /// `error`, which must be a string literal
+    ThrowNotSupported(String),
+    /// `assert(test, "message")` is a statement, whose message must be a string literal
+    AssertNotSupported,
+    /// `for` loops need a condition, there is no `break`
+    ForWithoutTestNotSupported,
+    /// Assignment to a `const`
+    ConstReassignment(String),
+    /// Assignment to a variable that is not declared in the function,
+    /// which includes the variables captured by a closure
+    AssignmentToUndeclared(String),
+    /// A variable used by a stripped trace is defined nowhere, see [Traces::Strip]
+    UndefinedInTrace(String),
     TypeNotSupported(String),
     PatNotSupported,
+    /// Object patterns cannot have default values or a rest element
+    ObjectPatNotSupported,
     ReturnTypeUnsupported(Box<IRError>),
 }
 
 pub type IRResult<A> = Result<A, IRError>;
 
+/// The value of the property `discriminant`, if it has a string literal type.
+fn tag_of(properties: &[(IRIdent, &TsType, bool)], discriminant: &str) -> Option<String> {
+    properties.iter().find_map(|(name, ty, optional)| match ty {
+        TsType::TsLitType(TsLitType {
+            lit: TsLit::Str(tag),
+            ..
+        }) if name.ident == discriminant && !optional => Some(tag.value.to_string()),
+        _ => None,
+    })
+}
+
+/// The name of a destructured parameter until it gets its final name, which cannot clash
+/// with the names in the source, see [JsToIR::visit_params]
+fn destructured_param(index: usize) -> String {
+    format!("#param_{}", index)
+}
+
+/// Whether `call` is `assert(...)`, see [JsToIR::visit_assert]
+fn is_assert(call: &CallExpr) -> bool {
+    matches!(&call.callee, Callee::Expr(callee)
+        if matches!(callee.as_ref(), Expr::Ident(ident) if &*ident.sym == "assert"))
+}
+
+/// Whether `new` is `new Error(...)`, see [JsToIR::visit_throwstmt]
+fn is_new_error(new: &NewExpr) -> bool {
+    matches!(new.callee.as_ref(), Expr::Ident(ident) if &*ident.sym == "Error")
+}
+
+/// Whether `decl` declares `name`, as a function or a variable
+fn declares(decl: &Decl, name: &str) -> bool {
+    match decl {
+        Decl::Fn(fndecl) => &*fndecl.ident.sym == name,
+        Decl::Var(vardecl) => vardecl.decls.iter().any(
+            |decl| matches!(&decl.name, Pat::Ident(BindingIdent { id, .. }) if &*id.sym == name),
+        ),
+        _ => false,
+    }
+}
+
+/// Whether the top-level `item` declares or imports `name`
+fn item_declares(item: &ModuleItem, name: &str) -> bool {
+    match item {
+        ModuleItem::Stmt(Stmt::Decl(decl)) => declares(decl, name),
+        ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export)) => declares(&export.decl, name),
+        ModuleItem::ModuleDecl(ModuleDecl::Import(import)) => {
+            import.specifiers.iter().any(|specifier| match specifier {
+                ImportSpecifier::Named(named) => &*named.local.sym == name,
+                ImportSpecifier::Default(default) => &*default.local.sym == name,
+                ImportSpecifier::Namespace(namespace) => &*namespace.local.sym == name,
+            })
+        }
+        _ => false,
+    }
+}
+
+/// Whether `call` is `console.log(...)`, see [JsToIR::visit_console_log]
+fn is_console_log(call: &CallExpr) -> bool {
+    let callee = match &call.callee {
+        Callee::Expr(callee) => callee.as_ref(),
+        _ => return false,
+    };
+
+    matches!(callee, Expr::Member(MemberExpr { obj, prop: MemberProp::Ident(prop), .. })
+        if &*prop.sym == "log"
+            && matches!(obj.as_ref(), Expr::Ident(obj) if &*obj.sym == "console"))
+}
+
+/// `stmts` without their traces, at any depth. Those of nested functions have been stripped
+/// when they were visited.
+fn without_traces(stmts: Vec<IR>) -> Vec<IR> {
+    stmts
+        .into_iter()
+        .filter(|stmt| !matches!(stmt, IR::TraceStmt(_)))
+        .map(without_trace)
+        .collect()
+}
+
+fn without_trace(stmt: IR) -> IR {
+    let block = |block: IRBlockStmt| IRBlockStmt {
+        body: without_traces(block.body),
+    };
+
+    match stmt {
+        IR::TraceStmt(_) => IR::BlockStmt(IRBlockStmt::default()),
+        IR::BlockStmt(blockstmt) => IR::BlockStmt(block(blockstmt)),
+        IR::IfStmt(IRIfStmt { _if, _then, _else }) => IR::IfStmt(IRIfStmt {
+            _if,
+            _then: Box::new(without_trace(*_then)),
+            _else: Box::new(without_trace(*_else)),
+        }),
+        IR::SwitchStmt(switchstmt) => IR::SwitchStmt(IRSwitchStmt {
+            cases: switchstmt
+                .cases
+                .into_iter()
+                .map(|case| IRSwitchCase {
+                    body: block(case.body),
+                    ..case
+                })
+                .collect(),
+            ..switchstmt
+        }),
+        IR::ForOfStmt(forofstmt) => IR::ForOfStmt(IRForOfStmt {
+            body: block(forofstmt.body),
+            ..forofstmt
+        }),
+        IR::WhileStmt(whilestmt) => IR::WhileStmt(IRWhileStmt {
+            body: block(whilestmt.body),
+            ..whilestmt
+        }),
+        stmt => stmt,
+    }
+}
+
+/// The aiken constructor for the member of the union `type_name` with the tag `tag`.
+/// It is prefixed with the type name, so that unions that share a tag, or a tag like `"some"`,
+/// do not clash, and the tag is capitalised word by word, without the characters that are not
+/// allowed in names, e.g. the tag `"claim-all"` of `Action` gives `ActionClaimAll`.
+pub fn constructor_name_of_tag(type_name: &str, tag: &str) -> String {
+    let mut name = type_name.to_string();
+    for word in tag.split(|c: char| !c.is_ascii_alphanumeric()) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            name.extend(first.to_uppercase().chain(chars));
+        }
+    }
+
+    name
+}
+
+/// The aiken values that the type definition `ir` introduces, that is its constructors
+fn constructor_names(ir: &IR) -> Vec<&String> {
+    match ir {
+        IR::TypeDef(typedef) => typedef
+            .constructors
+            .iter()
+            .map(|constructor| &constructor.name.ident)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Two types whose constructors have the same name, e.g. the union `A` with the tag `"b-c"`
+/// and the union `AB` with the tag `"c"`, are rejected.
+fn check_constructor_names(body: &[IR]) -> IRResult<()> {
+    let mut seen = HashSet::new();
+    for name in body.iter().flat_map(constructor_names) {
+        if !seen.insert(name) {
+            return Err(IRError::ConstructorNameClash(name.clone()));
+        }
+    }
+
+    Ok(())
+}
+
 impl From<IRError> for Error {
     fn from(e: IRError) -> Self {
         Error::IR(e)
@@ -45,22 +291,104 @@ impl From<IRError> for Error {
 }
 
 impl JsToIR {
+    pub fn new(options: CompilerOptions) -> Self {
+        JsToIR {
+            options,
+            type_params: RefCell::default(),
+            own_assert: Cell::default(),
+            traced_vars: RefCell::default(),
+        }
+    }
+
     pub fn visit_program(&self, program: &Program) -> IRResult<IR> {
         match program {
-            Program::Module(module) => Err(IRError::ModulesNotSupported),
+            Program::Module(module) => self.visit_module(module),
             Program::Script(script) => self.visit_script(script),
         }
     }
 
-    pub fn visit_script(&self, script: &Script) -> IRResult<IR> {
-        let stmts = &script.body;
+    /// An ES module is lowered like a script, with its `import` declarations kept around
+    /// as [IR::Import] nodes, so that the driver can resolve and compile the dependencies.
+    pub fn visit_module(&self, module: &Module) -> IRResult<IR> {
+        let items = &module.body;
+        self.own_assert
+            .set(items.iter().any(|item| item_declares(item, "assert")));
 
-        let mut body = Vec::with_capacity(stmts.len());
-        for stmt in stmts {
-            let ir = self.visit_stmt(stmt)?;
-            body.push(ir)
+        let mut body = Vec::with_capacity(items.len());
+        for item in items {
+            body.extend(self.visit_module_item(item)?);
+        }
+        let body = self.check_traced_vars(body)?;
+        check_constructor_names(&body)?;
+
+        Ok(IR::Script(IRScript { body }))
+    }
+
+    pub fn visit_module_item(&self, item: &ModuleItem) -> IRResult<Vec<IR>> {
+        match item {
+            ModuleItem::Stmt(stmt) => self.visit_stmts([stmt]),
+            ModuleItem::ModuleDecl(module_decl) => self.visit_module_decl(module_decl),
+        }
+    }
+
+    pub fn visit_module_decl(&self, module_decl: &ModuleDecl) -> IRResult<Vec<IR>> {
+        match module_decl {
+            ModuleDecl::Import(import) => Ok(vec![IR::Import(self.visit_import(import)?)]),
+            // Every top-level definition is visible from other modules anyway,
+            // so `export` does not need any special treatment.
+            ModuleDecl::ExportDecl(export) => self.visit_decls(&export.decl),
+            // `export { f, g }`, again nothing to do.
+            ModuleDecl::ExportNamed(export) if export.src.is_none() => Ok(vec![]),
+            _ => Err(IRError::ModuleDeclNotSupported),
+        }
+    }
+
+    pub fn visit_import(&self, import: &ImportDecl) -> IRResult<IRImport> {
+        let src = import.src.value.to_string();
+
+        let mut specifiers = Vec::with_capacity(import.specifiers.len());
+        for specifier in &import.specifiers {
+            match specifier {
+                ImportSpecifier::Named(named) => {
+                    let local = self.visit_ident(&named.local)?;
+                    let imported = match &named.imported {
+                        None => None,
+                        Some(ModuleExportName::Ident(ident)) => Some(self.visit_ident(ident)?),
+                        Some(ModuleExportName::Str(_)) => {
+                            return Err(IRError::ImportNotSupported(src))
+                        }
+                    };
+
+                    // `import { f as g }` imports `f` under the local name `g`
+                    let (name, alias) = match imported {
+                        None => (local, None),
+                        Some(imported) => (imported, Some(local)),
+                    };
+
+                    let name = Box::new(name);
+                    specifiers.push(IRImportSpecifier { name, alias });
+                }
+                // Default and namespace imports have no counterpart in aiken
+                _ => return Err(IRError::ImportNotSupported(src)),
+            }
         }
 
+        Ok(IRImport {
+            specifiers,
+            src,
+            module: None,
+        })
+    }
+
+    pub fn visit_script(&self, script: &Script) -> IRResult<IR> {
+        self.own_assert
+            .set(script.body.iter().any(|stmt| match stmt {
+                Stmt::Decl(decl) => declares(decl, "assert"),
+                _ => false,
+            }));
+        let body = self.check_traced_vars(self.visit_stmts(&script.body)?)?;
+        check_constructor_names(&body)?;
+
         Ok(IR::Script(IRScript { body }))
     }
 
@@ -70,16 +398,195 @@ impl JsToIR {
         match stmt {
             Stmt::Block(blockkstmt) => self.visit_blockstmt(blockkstmt).map(IR::BlockStmt),
             Stmt::Decl(decl) => self.visit_decl(decl),
-            Stmt::Expr(expr) => self.visit_exprstmt(expr).map(IR::ExprStmt),
+            Stmt::Expr(expr) => self.visit_exprstmt(expr),
             Stmt::If(ifstmt) => self.visit_ifstmt(ifstmt).map(IR::IfStmt),
             Stmt::Return(returnstm) => self.visit_returnstm(returnstm).map(IR::ReturnStmt),
+            Stmt::Throw(throwstmt) => self.visit_throwstmt(throwstmt).map(IR::ThrowStmt),
+            Stmt::Switch(switchstmt) => self.visit_switchstmt(switchstmt).map(IR::SwitchStmt),
+            Stmt::ForOf(forofstmt) => self.visit_forofstmt(forofstmt).map(IR::ForOfStmt),
+            Stmt::While(whilestmt) => self.visit_whilestmt(whilestmt).map(IR::WhileStmt),
+            Stmt::For(forstmt) => self.visit_forstmt(forstmt).map(IR::BlockStmt),
             _ => Err(IRError::StatementNotSupported),
         }
     }
 
-    pub fn visit_exprstmt(&self, expr: &ExprStmt) -> IRResult<IRExprStmt> {
-        let expr = self.visit_expr(expr.expr.as_ref());
-        expr.map(|expr| IRExprStmt { expr })
+    pub fn visit_exprstmt(&self, expr: &ExprStmt) -> IRResult<IR> {
+        self.visit_expr_as_stmt(expr.expr.as_ref())
+    }
+
+    /// Expressions evaluated for their effect on variables, e.g. `x += 1` or `i++`
+    fn visit_expr_as_stmt(&self, expr: &Expr) -> IRResult<IR> {
+        match expr {
+            Expr::Assign(assign) => self.visit_assign(assign).map(IR::AssignStmt),
+            Expr::Update(update) => self.visit_update(update).map(IR::AssignStmt),
+            Expr::Call(call) if is_assert(call) && !self.own_assert.get() => {
+                self.visit_assert(call).map(IR::IfStmt)
+            }
+            Expr::Call(call) if is_console_log(call) => self.visit_console_log(call),
+            expr => {
+                let expr = self.visit_expr(expr)?;
+                Ok(IR::ExprStmt(IRExprStmt { expr }))
+            }
+        }
+    }
+
+    /// `x = value`, `x += value`, etc.
+    pub fn visit_assign(&self, assign: &AssignExpr) -> IRResult<IRAssignStmt> {
+        let name = match &assign.left {
+            PatOrExpr::Pat(pat) => match pat.as_ref() {
+                Pat::Ident(BindingIdent { id, .. }) => self.visit_ident(id)?,
+                Pat::Expr(expr) => match expr.as_ref() {
+                    Expr::Ident(ident) => self.visit_ident(ident)?,
+                    _ => return Err(IRError::AssignmentNotSupported),
+                },
+                _ => return Err(IRError::AssignmentNotSupported),
+            },
+            PatOrExpr::Expr(expr) => match expr.as_ref() {
+                Expr::Ident(ident) => self.visit_ident(ident)?,
+                _ => return Err(IRError::AssignmentNotSupported),
+            },
+        };
+
+        let op = match assign.op {
+            AssignOp::Assign => None,
+            AssignOp::AddAssign => Some(IRBinOp::Add),
+            AssignOp::SubAssign => Some(IRBinOp::Sub),
+            AssignOp::MulAssign => Some(IRBinOp::Mul),
+            AssignOp::DivAssign => Some(IRBinOp::Div),
+            AssignOp::ModAssign => Some(IRBinOp::Mod),
+            _ => return Err(IRError::AssignmentNotSupported),
+        };
+
+        let value = self.visit_expr(assign.right.as_ref())?;
+        let value = match op {
+            None => value,
+            Some(op) => IRExpr::Binary(Box::new(IRBinaryExpr {
+                op,
+                left: Box::new(IRExpr::Identifier(name.clone())),
+                right: Box::new(value),
+            })),
+        };
+
+        Ok(IRAssignStmt {
+            name: Box::new(name),
+            value,
+        })
+    }
+
+    /// `i++`, `--i`, etc. which are only supported as statements, so `x = i++` is rejected
+    pub fn visit_update(&self, update: &UpdateExpr) -> IRResult<IRAssignStmt> {
+        let name = match update.arg.as_ref() {
+            Expr::Ident(ident) => self.visit_ident(ident)?,
+            _ => return Err(IRError::AssignmentNotSupported),
+        };
+
+        let op = match update.op {
+            UpdateOp::PlusPlus => IRBinOp::Add,
+            UpdateOp::MinusMinus => IRBinOp::Sub,
+        };
+        // With only `bigint`s, there is no `number` literal for the step
+        let one = match self.options.numbers {
+            NumericSemantics::BigIntOnly => IRLiteral::BigInt(BigInt::from(1)),
+            _ => IRLiteral::Float64(1.0),
+        };
+        let value = IRExpr::Binary(Box::new(IRBinaryExpr {
+            op,
+            left: Box::new(IRExpr::Identifier(name.clone())),
+            right: Box::new(IRExpr::Literal(one)),
+        }));
+
+        Ok(IRAssignStmt {
+            name: Box::new(name),
+            value,
+        })
+    }
+
+    /// The body of a loop, which cannot `return`, although it can `throw`
+    fn visit_loop_body(&self, body: &Stmt) -> IRResult<IRBlockStmt> {
+        let body = match self.visit_stmt(body)? {
+            IR::BlockStmt(body) => body,
+            stmt => IRBlockStmt { body: vec![stmt] },
+        };
+
+        if body.body.iter().any(may_return_value) {
+            return Err(IRError::ReturnInLoopNotSupported);
+        }
+
+        Ok(body)
+    }
+
+    /// `while (test) { ... }`
+    pub fn visit_whilestmt(&self, whilestmt: &WhileStmt) -> IRResult<IRWhileStmt> {
+        let test = self.visit_expr(whilestmt.test.as_ref())?;
+        let body = self.visit_loop_body(whilestmt.body.as_ref())?;
+
+        Ok(IRWhileStmt {
+            test: Box::new(test),
+            body,
+        })
+    }
+
+    /// `for (init; test; update) { ... }` becomes `{ init; while (test) { ...; update } }`
+    pub fn visit_forstmt(&self, forstmt: &ForStmt) -> IRResult<IRBlockStmt> {
+        let mut stmts = Vec::with_capacity(2);
+        match &forstmt.init {
+            None => {}
+            Some(VarDeclOrExpr::VarDecl(vardecl)) => {
+                stmts.extend(self.visit_vardecl(vardecl)?);
+            }
+            Some(VarDeclOrExpr::Expr(expr)) => stmts.push(self.visit_expr_as_stmt(expr)?),
+        }
+
+        let test = match &forstmt.test {
+            None => return Err(IRError::ForWithoutTestNotSupported),
+            Some(test) => self.visit_expr(test.as_ref())?,
+        };
+
+        let mut body = self.visit_loop_body(forstmt.body.as_ref())?;
+        if let Some(update) = &forstmt.update {
+            match update.as_ref() {
+                // `i++, j--`
+                Expr::Seq(seq) => {
+                    for expr in &seq.exprs {
+                        body.body.push(self.visit_expr_as_stmt(expr)?);
+                    }
+                }
+                update => body.body.push(self.visit_expr_as_stmt(update)?),
+            }
+        }
+
+        stmts.push(IR::WhileStmt(IRWhileStmt {
+            test: Box::new(test),
+            body,
+        }));
+
+        Ok(IRBlockStmt { body: stmts })
+    }
+
+    /// `for (const item of list) { ... }`
+    pub fn visit_forofstmt(&self, forofstmt: &ForOfStmt) -> IRResult<IRForOfStmt> {
+        if forofstmt.is_await {
+            return Err(IRError::ForOfNotSupported);
+        }
+
+        let item = match &forofstmt.left {
+            VarDeclOrPat::VarDecl(vardecl) => match vardecl.decls.as_slice() {
+                [decl] if vardecl.kind != VarDeclKind::Var && decl.init.is_none() => {
+                    self.visit_pat_as_binding_ident(&decl.name)?.name
+                }
+                _ => return Err(IRError::ForOfNotSupported),
+            },
+            VarDeclOrPat::Pat(_) => return Err(IRError::ForOfNotSupported),
+        };
+
+        let list = self.visit_expr(forofstmt.right.as_ref())?;
+        let body = self.visit_loop_body(forofstmt.body.as_ref())?;
+
+        Ok(IRForOfStmt {
+            item,
+            list: Box::new(list),
+            body,
+        })
     }
 
     pub fn visit_returnstm(&self, ret: &ReturnStmt) -> IRResult<IRReturnStmt> {
@@ -93,18 +600,384 @@ impl JsToIR {
         }
     }
 
+    /// `throw new Error("message")`
+    pub fn visit_throwstmt(&self, throwstmt: &ThrowStmt) -> IRResult<IRThrowStmt> {
+        let not_supported = || IRError::ThrowNotSupported(format!("{:?}", throwstmt.arg));
+
+        let args = match throwstmt.arg.as_ref() {
+            Expr::New(new) if is_new_error(new) => new.args.as_deref().unwrap_or_default(),
+            _ => return Err(not_supported()),
+        };
+        let message = match args {
+            [] => None,
+            [ExprOrSpread { spread: None, expr }] => match expr.as_ref() {
+                Expr::Lit(Lit::Str(message)) => Some(message.value.to_string()),
+                _ => return Err(not_supported()),
+            },
+            _ => return Err(not_supported()),
+        };
+
+        Ok(IRThrowStmt { message })
+    }
+
+    /// `assert(test, "message")`, from our prelude, is `if (!test) throw new Error("message")`.
+    /// The message is optional.
+    fn visit_assert(&self, call: &CallExpr) -> IRResult<IRIfStmt> {
+        let mut args = Vec::with_capacity(call.args.len());
+        for arg in &call.args {
+            if arg.spread.is_some() {
+                return Err(IRError::SpreadNotSupported);
+            }
+            args.push(arg.expr.as_ref());
+        }
+
+        let (test, message) = match args.as_slice() {
+            [test] => (test, None),
+            [test, Expr::Lit(Lit::Str(message))] => (test, Some(message.value.to_string())),
+            _ => return Err(IRError::AssertNotSupported),
+        };
+
+        let _if = IRExpr::Unary(Box::new(IRUnaryExpr {
+            op: IRUnOp::Not,
+            expr: Box::new(self.visit_expr(test)?),
+        }));
+        let _then = IR::ThrowStmt(IRThrowStmt { message });
+
+        Ok(IRIfStmt {
+            _if: Box::new(_if),
+            _then: Box::new(_then),
+            _else: Box::new(IR::BlockStmt(IRBlockStmt::default())),
+        })
+    }
+
+    /// `console.log(a, b)` traces its arguments separated by spaces, like the template literal
+    /// `` `${a} ${b}` ``. Traces are stripped from the function that contains them, see
+    /// [Self::strip_traces].
+    fn visit_console_log(&self, call: &CallExpr) -> IRResult<IR> {
+        let mut exprs = Vec::with_capacity(call.args.len());
+        for arg in &call.args {
+            if arg.spread.is_some() {
+                return Err(IRError::SpreadNotSupported);
+            }
+            exprs.push(self.visit_expr(arg.expr.as_ref())?);
+        }
+
+        let mut quasis = vec![String::new()];
+        for i in 1..=exprs.len() {
+            let separator = if i < exprs.len() { " " } else { "" };
+            quasis.push(separator.to_string());
+        }
+
+        let message = IRExpr::Template(Box::new(IRTemplate { quasis, exprs }));
+
+        Ok(IR::TraceStmt(IRTraceStmt { message }))
+    }
+
     pub fn visit_expr(&self, expr: &Expr) -> IRResult<IRExpr> {
         // let debug = format!("{:?}", expr);
 
         match expr {
+            Expr::Unary(expr) => self.visit_unaryexpr(expr).map(Box::new).map(IRExpr::Unary),
             Expr::Bin(expr) => self.visit_binexpr(expr).map(Box::new).map(IRExpr::Binary),
+            Expr::Ident(expr) if &*expr.sym == "undefined" => {
+                Ok(IRExpr::Literal(IRLiteral::Undefined))
+            }
             Expr::Ident(expr) => self.visit_ident(expr).map(IRExpr::Identifier),
             Expr::Lit(expr) => self.visit_lit(expr).map(IRExpr::Literal),
             Expr::Paren(expr) => self.visit_paren_expr(expr),
+            Expr::Cond(expr) => self.visit_condexpr(expr).map(Box::new).map(IRExpr::Cond),
+            Expr::Object(expr) => self.visit_object(expr).map(Box::new).map(IRExpr::Object),
+            Expr::Member(expr) => self.visit_member(expr),
+            Expr::OptChain(expr) => self.visit_opt_chain(expr),
+            Expr::Array(expr) => self.visit_array(expr).map(Box::new).map(IRExpr::Array),
+            Expr::Call(expr) => self.visit_callexpr(expr),
+            Expr::Tpl(expr) => self.visit_tpl(expr).map(Box::new).map(IRExpr::Template),
+            Expr::TaggedTpl(expr) => self.visit_tagged_tpl(expr).map(IRExpr::Literal),
+            Expr::Arrow(expr) => self.visit_arrow(expr).map(Box::new).map(IRExpr::Lambda),
+            Expr::Fn(expr) => self.visit_fnexpr(expr).map(Box::new).map(IRExpr::Lambda),
             _ => Err(IRError::ExpressionNotSupported(format!("{:?}", expr))),
         }
     }
 
+    pub fn visit_condexpr(&self, expr: &CondExpr) -> IRResult<IRCondExpr> {
+        let _if = self.visit_expr(expr.test.as_ref())?;
+        let _then = self.visit_expr(expr.cons.as_ref())?;
+        let _else = self.visit_expr(expr.alt.as_ref())?;
+
+        Ok(IRCondExpr {
+            _if: Box::new(_if),
+            _then: Box::new(_then),
+            _else: Box::new(_else),
+        })
+    }
+
+    pub fn visit_object(&self, expr: &ObjectLit) -> IRResult<IRObjectLit> {
+        let mut props = Vec::with_capacity(expr.props.len());
+        for prop in &expr.props {
+            let prop = match prop {
+                PropOrSpread::Prop(prop) => prop.as_ref(),
+                PropOrSpread::Spread(_) => return Err(IRError::SpreadNotSupported),
+            };
+
+            let (name, value) = match prop {
+                // `{ owner: o }`
+                Prop::KeyValue(KeyValueProp { key, value }) => {
+                    let name = self.visit_prop_name(key)?;
+                    let value = self.visit_expr(value.as_ref())?;
+                    (name, value)
+                }
+                // `{ owner }`
+                Prop::Shorthand(ident) => {
+                    let name = self.visit_ident(ident)?;
+                    let value = IRExpr::Identifier(self.visit_ident(ident)?);
+                    (name, value)
+                }
+                _ => return Err(IRError::PropNotSupported),
+            };
+
+            props.push(IRProp {
+                name: Box::new(name),
+                value,
+            });
+        }
+
+        Ok(IRObjectLit { props })
+    }
+
+    fn visit_prop_name(&self, prop_name: &PropName) -> IRResult<IRIdent> {
+        match prop_name {
+            PropName::Ident(ident) => self.visit_ident(ident),
+            PropName::Str(str) => Ok(IRIdent {
+                ident: str.value.to_string(),
+            }),
+            _ => Err(IRError::PropNotSupported),
+        }
+    }
+
+    /// `object?.prop`. Optional calls, `f?.()`, are not supported since functions are never
+    /// optional values.
+    fn visit_opt_chain(&self, opt_chain: &OptChainExpr) -> IRResult<IRExpr> {
+        match &opt_chain.base {
+            OptChainBase::Member(MemberExpr {
+                obj,
+                prop: MemberProp::Ident(ident),
+                ..
+            }) => {
+                let object = Box::new(self.visit_expr(obj.as_ref())?);
+                let prop = Box::new(self.visit_ident(ident)?);
+
+                Ok(IRExpr::OptMember(Box::new(IRMember { object, prop })))
+            }
+            _ => Err(IRError::ExpressionNotSupported(format!("{:?}", opt_chain))),
+        }
+    }
+
+    /// `object.prop`, or `object[0]`
+    pub fn visit_member(&self, expr: &MemberExpr) -> IRResult<IRExpr> {
+        let object = Box::new(self.visit_expr(expr.obj.as_ref())?);
+        match &expr.prop {
+            MemberProp::Ident(ident) => {
+                let prop = Box::new(self.visit_ident(ident)?);
+                let member = IRMember { object, prop };
+
+                Ok(IRExpr::Member(Box::new(member)))
+            }
+            MemberProp::Computed(ComputedPropName { expr, .. }) => match expr.as_ref() {
+                Expr::Lit(Lit::Num(number))
+                    if number.value >= 0.0 && number.value.fract() == 0.0 =>
+                {
+                    let index = number.value as usize;
+                    let index = IRIndex { object, index };
+
+                    Ok(IRExpr::Index(Box::new(index)))
+                }
+                _ => Err(IRError::IndexNotSupported),
+            },
+            _ => Err(IRError::PropNotSupported),
+        }
+    }
+
+    pub fn visit_array(&self, expr: &ArrayLit) -> IRResult<IRArrayLit> {
+        let mut elements = Vec::with_capacity(expr.elems.len());
+        for element in &expr.elems {
+            match element {
+                None => return Err(IRError::ArrayHoleNotSupported),
+                Some(ExprOrSpread {
+                    spread: Some(_), ..
+                }) => return Err(IRError::SpreadNotSupported),
+                Some(ExprOrSpread { expr, .. }) => elements.push(self.visit_expr(expr.as_ref())?),
+            }
+        }
+
+        Ok(IRArrayLit { elements })
+    }
+
+    pub fn visit_callexpr(&self, expr: &CallExpr) -> IRResult<IRExpr> {
+        // We only support calling functions by their name, and a few functions of `Math`
+        let name = match &expr.callee {
+            Callee::Expr(callee) => match callee.as_ref() {
+                Expr::Ident(ident) if &*ident.sym == "assert" && !self.own_assert.get() => {
+                    return Err(IRError::AssertNotSupported)
+                }
+                Expr::Ident(ident) => self.visit_ident(ident)?,
+                Expr::Member(MemberExpr {
+                    obj,
+                    prop: MemberProp::Ident(prop),
+                    ..
+                }) if matches!(obj.as_ref(), Expr::Ident(obj) if &*obj.sym == "Math") => {
+                    let builtin = self.visit_math_fn(&prop.sym)?;
+                    let mut args = Vec::with_capacity(expr.args.len());
+                    for arg in &expr.args {
+                        if arg.spread.is_some() {
+                            return Err(IRError::SpreadNotSupported);
+                        }
+                        args.push(self.visit_expr(arg.expr.as_ref())?);
+                    }
+
+                    return Ok(IRExpr::Builtin(Box::new(IRBuiltinCall { builtin, args })));
+                }
+                // `bytes.slice(start, end)` and `bytes.concat(other)`
+                Expr::Member(MemberExpr {
+                    obj,
+                    prop: MemberProp::Ident(prop),
+                    ..
+                }) if &*prop.sym == "slice" || &*prop.sym == "concat" => {
+                    let builtin = match &*prop.sym {
+                        "slice" => IRBuiltin::Slice,
+                        _ => IRBuiltin::Concat,
+                    };
+                    let mut args = vec![self.visit_expr(obj.as_ref())?];
+                    for arg in &expr.args {
+                        if arg.spread.is_some() {
+                            return Err(IRError::SpreadNotSupported);
+                        }
+                        args.push(self.visit_expr(arg.expr.as_ref())?);
+                    }
+
+                    return Ok(IRExpr::Builtin(Box::new(IRBuiltinCall { builtin, args })));
+                }
+                callee => return Err(IRError::CalleeNotSupported(format!("{:?}", callee))),
+            },
+            callee => return Err(IRError::CalleeNotSupported(format!("{:?}", callee))),
+        };
+
+        let mut args = Vec::with_capacity(expr.args.len());
+        let mut spread = None;
+        for arg in &expr.args {
+            // Only the last argument can be spread, for a rest parameter
+            if spread.is_some() {
+                return Err(IRError::SpreadNotSupported);
+            }
+
+            let value = self.visit_expr(arg.expr.as_ref())?;
+            match arg.spread {
+                None => args.push(value),
+                Some(_) => spread = Some(Box::new(value)),
+            }
+        }
+
+        Ok(IRExpr::Apply(Box::new(IRApply {
+            name: Box::new(name),
+            args,
+            spread,
+        })))
+    }
+
+    /// `` `owner: ${name}` ``
+    pub fn visit_tpl(&self, tpl: &Tpl) -> IRResult<IRTemplate> {
+        let mut quasis = Vec::with_capacity(tpl.quasis.len());
+        for quasi in &tpl.quasis {
+            // The cooked string is missing when it has an invalid escape sequence
+            let cooked = quasi.cooked.as_ref().ok_or(IRError::LiteralNotSupported)?;
+            quasis.push(cooked.to_string());
+        }
+
+        let mut exprs = Vec::with_capacity(tpl.exprs.len());
+        for expr in &tpl.exprs {
+            exprs.push(self.visit_expr(expr.as_ref())?);
+        }
+
+        Ok(IRTemplate { quasis, exprs })
+    }
+
+    /// `` hex`deadbeef` ``, a byte array known at compile time
+    pub fn visit_tagged_tpl(&self, tagged_tpl: &TaggedTpl) -> IRResult<IRLiteral> {
+        let is_hex = matches!(tagged_tpl.tag.as_ref(), Expr::Ident(tag) if &*tag.sym == "hex");
+        if !is_hex {
+            return Err(IRError::TaggedTemplateNotSupported);
+        }
+
+        let hex = match (tagged_tpl.tpl.quasis.as_slice(), tagged_tpl.tpl.exprs.len()) {
+            ([quasi], 0) => quasi.raw.to_string(),
+            _ => return Err(IRError::HexLiteralNotSupported("${...}".to_string())),
+        };
+
+        hex::decode(&hex)
+            .map(IRLiteral::ByteArray)
+            .map_err(|_| IRError::HexLiteralNotSupported(hex))
+    }
+
+    /// `Math.name`, as in `Math.max(a, b)`
+    fn visit_math_fn(&self, name: &str) -> IRResult<IRBuiltin> {
+        let not_supported = |reason: &str| {
+            Err(IRError::MathNotSupported {
+                function: name.to_string(),
+                reason: reason.to_string(),
+            })
+        };
+
+        match name {
+            "abs" => Ok(IRBuiltin::MathAbs),
+            "min" => Ok(IRBuiltin::MathMin),
+            "max" => Ok(IRBuiltin::MathMax),
+            "pow" => Ok(IRBuiltin::MathPow),
+            "random" => not_supported(
+                "on-chain code must be deterministic, every node validating a transaction must get the same result",
+            ),
+            "sqrt" | "cbrt" | "hypot" | "exp" | "expm1" | "log" | "log10" | "log1p" | "log2"
+            | "sin" | "cos" | "tan" | "asin" | "acos" | "atan" | "atan2" | "sinh" | "cosh"
+            | "tanh" | "asinh" | "acosh" | "atanh" => not_supported(
+                "the result is not an integer in general, and there are only integers on-chain",
+            ),
+            _ => not_supported("there is no on-chain equivalent"),
+        }
+    }
+
+    pub fn visit_unaryexpr(&self, expr: &UnaryExpr) -> IRResult<IRUnaryExpr> {
+        let op = self.visit_unaryop(&expr.op)?;
+        let expr = self.visit_expr(expr.arg.as_ref())?;
+
+        Ok(IRUnaryExpr {
+            op,
+            expr: Box::new(expr),
+        })
+    }
+
+    pub fn visit_unaryop(&self, op: &UnaryOp) -> IRResult<IRUnOp> {
+        let not_supported = |reason: &str| {
+            Err(IRError::UnaryOpNotSupported {
+                op: op.as_str().to_string(),
+                reason: reason.to_string(),
+            })
+        };
+
+        match op {
+            UnaryOp::Bang => Ok(IRUnOp::Not),
+            UnaryOp::Minus => Ok(IRUnOp::Minus),
+            UnaryOp::Plus => Ok(IRUnOp::Plus),
+            UnaryOp::Tilde => not_supported("there are no bitwise operations on-chain"),
+            UnaryOp::TypeOf => not_supported(
+                "types are known at compile time and there is no runtime type information on-chain",
+            ),
+            UnaryOp::Void => not_supported(
+                "on-chain code has no side effects, so evaluating an expression only to discard it is pointless",
+            ),
+            UnaryOp::Delete => not_supported(
+                "on-chain values are immutable, so properties cannot be removed from objects",
+            ),
+        }
+    }
+
     pub fn visit_binexpr(&self, expr: &BinExpr) -> IRResult<IRBinaryExpr> {
         let op = self.visit_binaryop(&expr.op)?;
         let left_expr = expr.left.as_ref();
@@ -125,7 +998,8 @@ impl JsToIR {
         match op {
             BinaryOp::Add => Ok(IRBinOp::Add),
             BinaryOp::Div => Ok(IRBinOp::Div),
-            BinaryOp::EqEq => Ok(IRBinOp::EqEq),
+            // Operands must have the same type, so there is no coercion to tell them apart
+            BinaryOp::EqEq | BinaryOp::EqEqEq => Ok(IRBinOp::EqEq),
             BinaryOp::Gt => Ok(IRBinOp::Gt),
             BinaryOp::GtEq => Ok(IRBinOp::GtEq),
             BinaryOp::LogicalAnd => Ok(IRBinOp::LogicalAnd),
@@ -134,8 +1008,10 @@ impl JsToIR {
             BinaryOp::LtEq => Ok(IRBinOp::LtEq),
             BinaryOp::Mod => Ok(IRBinOp::Mod),
             BinaryOp::Mul => Ok(IRBinOp::Mul),
-            BinaryOp::NotEq => Ok(IRBinOp::NotEq),
+            BinaryOp::Exp => Ok(IRBinOp::Exp),
+            BinaryOp::NotEq | BinaryOp::NotEqEq => Ok(IRBinOp::NotEq),
             BinaryOp::Sub => Ok(IRBinOp::Sub),
+            BinaryOp::NullishCoalescing => Ok(IRBinOp::NullishCoalescing),
             _ => Err(IRError::BinaryOpNotSupported),
         }
     }
@@ -143,7 +1019,23 @@ impl JsToIR {
     pub fn visit_lit(&self, expr: &Lit) -> IRResult<IRLiteral> {
         match expr {
             Lit::Bool(lit) => Ok(IRLiteral::Boolean(lit.value)),
-            Lit::Num(lit) => Ok(IRLiteral::Float64(lit.value)),
+            Lit::Num(lit) => {
+                let raw = || match &lit.raw {
+                    Some(raw) => raw.to_string(),
+                    None => lit.value.to_string(),
+                };
+
+                // Plutus only has integers, so `1.5` would silently become something else
+                // and `1e21` would have already lost precision
+                match self.options.numbers {
+                    NumericSemantics::BigIntOnly => Err(IRError::NumberNotAllowed(raw())),
+                    numbers => match numbers.number_literal(lit.value) {
+                        Some(_) => Ok(IRLiteral::Float64(lit.value)),
+                        None => Err(IRError::NumberNotAnInteger(raw())),
+                    },
+                }
+            }
+            Lit::BigInt(lit) => Ok(IRLiteral::BigInt(lit.value.as_ref().clone())),
             Lit::Str(lit) => Ok(IRLiteral::String(lit.value.to_string())),
             _ => Err(IRError::LiteralNotSupported),
         }
@@ -156,10 +1048,45 @@ impl JsToIR {
         })
     }
 
+    /// Statements in sequence, where declaring several variables at once, `let a = 1, b = 2`,
+    /// becomes several definitions
+    fn visit_stmts<'a>(&self, stmts: impl IntoIterator<Item = &'a Stmt>) -> IRResult<Vec<IR>> {
+        let mut irs = Vec::new();
+        for stmt in stmts {
+            match stmt {
+                Stmt::Decl(decl) => irs.extend(self.visit_decls(decl)?),
+                stmt => irs.push(self.visit_stmt(stmt)?),
+            }
+        }
+
+        Ok(irs)
+    }
+
+    /// The definitions of a declaration, see [Self::visit_stmts]
+    fn visit_decls(&self, decl: &Decl) -> IRResult<Vec<IR>> {
+        match decl {
+            Decl::Var(vardecl) => self.visit_vardecl(vardecl.as_ref()),
+            // `type ByteArray = Uint8Array` names what aiken already calls `ByteArray`
+            Decl::TsTypeAlias(alias)
+                if &*alias.id.sym == "ByteArray"
+                    && matches!(self.visit_ts_type(&alias.type_ann), Ok(IRTy::ByteArrayTy)) =>
+            {
+                Ok(vec![])
+            }
+            decl => Ok(vec![self.visit_decl(decl)?]),
+        }
+    }
+
     pub fn visit_decl(&self, decl: &Decl) -> IRResult<IR> {
         match decl {
             Decl::Fn(fndecl) => self.visit_fndecl(fndecl).map(IR::FunDef),
-            Decl::Var(vardecl) => self.visit_vardecl(vardecl.as_ref()).map(IR::VarDef),
+            Decl::Var(vardecl) => {
+                let body = self.visit_vardecl(vardecl.as_ref())?;
+
+                Ok(IR::BlockStmt(IRBlockStmt { body }))
+            }
+            Decl::TsInterface(interface) => self.visit_interface(interface).map(IR::TypeDef),
+            Decl::TsTypeAlias(alias) => self.visit_type_alias(alias).map(IR::TypeDef),
             _ => Err(IRError::DeclarationNotSupported),
         }
     }
@@ -170,50 +1097,368 @@ impl JsToIR {
         let name = Box::new(ident);
 
         let function = fndecl.function.as_ref();
-        let fparams = &function.params;
+        let (params, arity, fun_ty, body) = self.visit_function(function)?;
 
-        // params
-        let plen = fparams.len();
-        let mut params = Vec::with_capacity(plen);
+        Ok(IRFunDef {
+            name,
+            params,
+            arity,
+            fun_ty,
+            body: Box::new(body),
+        })
+    }
+
+    /// The parts of a [Function] that are common to function declarations and function expressions.
+    pub fn visit_function(
+        &self,
+        function: &Function,
+    ) -> IRResult<(Vec<IRNameTy>, IRArity, IRFunTy, IRBlockStmt)> {
+        self.with_type_params(function.type_params.as_deref(), |type_params| {
+            // body, whose names the generated parameter names must avoid
+            let outer_traced_vars = self.traced_vars.take();
+            let ref_body = function.body.as_ref();
+            let mut body = ref_body
+                .map(|blockstmt| self.visit_blockstmt(blockstmt))
+                .unwrap_or_else(|| Ok(IRBlockStmt::default()))?;
+            body = self.strip_traces(body);
+
+            // params
+            let (params, arity, prologue) =
+                self.visit_params(function.params.iter().map(|param| &param.pat), &body)?;
+            self.resolve_traced_vars(outer_traced_vars, &params, &prologue, &body);
 
-        for fparam in fparams {
-            let irparam = self.visit_param(fparam)?;
-            params.push(irparam);
+            let ret_ty = self.visit_return_type(function.return_type.as_deref())?;
+
+            // ready to compute the function type
+            let param_tys = params.iter().map(|p| p.ty.clone()).collect();
+            let fun_ty = IRFunTy {
+                type_params,
+                param_tys,
+                ret_ty,
+            };
+
+            body.body.splice(0..0, prologue);
+            let body = ssa_body(&params, normalize_body(body))?;
+
+            Ok((params, arity, fun_ty, body))
+        })
+    }
+
+    /// With [Traces::Strip], `body` without its traces, once their messages have been lowered
+    /// like any other expression, so that a stripped build fails wherever one that keeps its
+    /// traces does. The variables that only the traces use are left to
+    /// [Self::resolve_traced_vars], since they may be defined outside of the function.
+    fn strip_traces(&self, body: IRBlockStmt) -> IRBlockStmt {
+        if self.options.traces != Traces::Strip {
+            return body;
         }
 
-        let ret_ty = match &function.return_type {
-            None => Ok(IRTy::UnknownTy),
-            Some(ts_type_ann) => self.visit_ts_type_ann(ts_type_ann.as_ref()),
+        let free = FreeVars::default().visit_blockstmt(&body);
+        let body = IRBlockStmt {
+            body: without_traces(body.body),
         };
+        let used = FreeVars::default().visit_blockstmt(&body);
+        self.traced_vars
+            .borrow_mut()
+            .extend(free.difference(&used).cloned());
 
-        if let Err(e) = ret_ty {
-            return Err(IRError::ReturnTypeUnsupported(Box::new(e)));
+        body
+    }
+
+    /// Drops the variables used by stripped traces that the function with `params`, `prologue`
+    /// and `body` defines, and hands the others over to the enclosing function, whose own
+    /// variables are `outer`.
+    fn resolve_traced_vars(
+        &self,
+        outer: Vars,
+        params: &[IRNameTy],
+        prologue: &[IR],
+        body: &IRBlockStmt,
+    ) {
+        let mut defined = names(&body.body);
+        defined.extend(names(prologue));
+        defined.extend(params.iter().map(|param| param.name.ident.clone()));
+
+        let mut traced_vars = self.traced_vars.replace(outer);
+        traced_vars.retain(|var| !defined.contains(var));
+        self.traced_vars.borrow_mut().extend(traced_vars);
+    }
+
+    /// The top-level statements `body`, without their traces if they are stripped, once the
+    /// variables that the stripped traces use are known to be defined by the module.
+    fn check_traced_vars(&self, body: Vec<IR>) -> IRResult<Vec<IR>> {
+        let body = self.strip_traces(IRBlockStmt { body }).body;
+
+        let defined = names(&body);
+        let traced_vars = self.traced_vars.take();
+        match traced_vars.into_iter().find(|var| !defined.contains(var)) {
+            Some(var) => Err(IRError::UndefinedInTrace(var)),
+            None => Ok(body),
         }
-        // now it is safe to unwrap
-        let ret_ty = Box::new(ret_ty.unwrap());
+    }
 
-        // ready to compute the function type
-        let param_tys = params.iter().map(|p| p.ty.clone()).collect();
-        let fun_ty = IRFunTy { param_tys, ret_ty };
+    /// Runs `f` with the type parameters of a generic function or type in scope, so that
+    /// references to them become [IRTy::VarTy]. `f` is given the names of their type variables.
+    fn with_type_params<R>(
+        &self,
+        type_params: Option<&TsTypeParamDecl>,
+        f: impl FnOnce(Vec<String>) -> IRResult<R>,
+    ) -> IRResult<R> {
+        // Type variables are lowercase in aiken, so `K` and `k` need different names
+        let mut used: Vars = self
+            .type_params
+            .borrow()
+            .iter()
+            .map(|(_, var)| var.clone())
+            .collect();
+        let mut params = vec![];
+        for param in type_params.iter().flat_map(|decl| &decl.params) {
+            let name = param.name.sym.to_string();
+            if param.constraint.is_some() || param.default.is_some() {
+                return Err(IRError::TypeParamNotSupported(name));
+            }
+            let var = fresh_name(&name.to_lowercase(), &used);
+            used.insert(var.clone());
+            params.push((name, var));
+        }
 
-        // body
-        let ref_body = function.body.as_ref();
-        let body = ref_body
-            .map(|blockstmt| self.visit_blockstmt(blockstmt))
-            .unwrap_or_else(|| Ok(IRBlockStmt::default()))?;
+        let vars = params.iter().map(|(_, var)| var.clone()).collect();
+        let outer = self.type_params.borrow().len();
+        self.type_params.borrow_mut().extend(params);
+        let result = f(vars);
+        self.type_params.borrow_mut().truncate(outer);
 
-        Ok(IRFunDef {
-            name,
-            params,
-            fun_ty,
-            body: Box::new(body),
+        result
+    }
+
+    fn visit_return_type(&self, return_type: Option<&TsTypeAnn>) -> IRResult<Box<IRTy>> {
+        let ret_ty = match return_type {
+            None => Ok(IRTy::UnknownTy),
+            Some(ts_type_ann) => self.visit_ts_type_ann(ts_type_ann),
+        };
+
+        ret_ty
+            .map(Box::new)
+            .map_err(|e| IRError::ReturnTypeUnsupported(Box::new(e)))
+    }
+
+    /// `function (x) { ... }`
+    pub fn visit_fnexpr(&self, fnexpr: &FnExpr) -> IRResult<IRLambda> {
+        let function = fnexpr.function.as_ref();
+        let (params, arity, fun_ty, body) = self.visit_function(function)?;
+
+        Ok(IRLambda::new(params, arity, fun_ty, body))
+    }
+
+    /// `(x) => x + 1` or `(x) => { ... }`
+    pub fn visit_arrow(&self, arrow: &ArrowExpr) -> IRResult<IRLambda> {
+        self.with_type_params(arrow.type_params.as_deref(), |type_params| {
+            let outer_traced_vars = self.traced_vars.take();
+            let body = match &arrow.body {
+                BlockStmtOrExpr::BlockStmt(blockstmt) => self.visit_blockstmt(blockstmt)?,
+                // An expression body is just a shorthand for returning the expression
+                BlockStmtOrExpr::Expr(expr) => {
+                    let expr = self.visit_expr(expr.as_ref())?;
+                    let ret = IR::ReturnStmt(IRReturnStmt { expr: Some(expr) });
+
+                    IRBlockStmt { body: vec![ret] }
+                }
+            };
+
+            let mut body = self.strip_traces(body);
+
+            let (params, arity, prologue) = self.visit_params(&arrow.params, &body)?;
+            self.resolve_traced_vars(outer_traced_vars, &params, &prologue, &body);
+
+            let ret_ty = self.visit_return_type(arrow.return_type.as_deref())?;
+
+            let param_tys = params.iter().map(|p| p.ty.clone()).collect();
+            let fun_ty = IRFunTy {
+                type_params,
+                param_tys,
+                ret_ty,
+            };
+
+            body.body.splice(0..0, prologue);
+            let body = ssa_body(&params, normalize_body(body))?;
+
+            Ok(IRLambda::new(params, arity, fun_ty, body))
         })
     }
 
-    pub fn visit_param(&self, param: &Param) -> IRResult<IRNameTy> {
-        let pat = &param.pat;
-        // We only support one style of function parameter declarations
-        self.visit_pat_as_binding_ident(pat)
+    /// Function parameters, along with their default values and whether the last one
+    /// is a rest parameter.
+    ///
+    /// A destructured parameter, `{ owner }: Datum`, gets a generated name, `param_0`, that
+    /// no other parameter and nothing in `body` uses, and is destructured by the returned
+    /// prologue, to be put at the start of the body.
+    pub fn visit_params<'a>(
+        &self,
+        pats: impl IntoIterator<Item = &'a Pat>,
+        body: &IRBlockStmt,
+    ) -> IRResult<(Vec<IRNameTy>, IRArity, Vec<IR>)> {
+        let mut params = Vec::new();
+        let mut arity = IRArity::default();
+        let mut prologue = Vec::new();
+
+        for (index, pat) in pats.into_iter().enumerate() {
+            let (param, default) = match pat {
+                // `fee: bigint = 1n`
+                Pat::Assign(AssignPat { left, right, .. }) => {
+                    let param = self.visit_param_pat(left.as_ref(), index, &mut prologue)?;
+                    let default = self.visit_expr(right.as_ref())?;
+                    (param, Some(default))
+                }
+                // `...xs: bigint[]`
+                Pat::Rest(RestPat { arg, type_ann, .. }) => {
+                    let mut param = self.visit_pat_as_binding_ident(arg.as_ref())?;
+                    param.ty = match type_ann {
+                        Some(ts_type_ann) => self.visit_ts_type_ann(ts_type_ann)?,
+                        None => match param.ty {
+                            IRTy::UnknownTy => IRTy::ListTy(Box::new(IRTy::UnknownTy)),
+                            ty => ty,
+                        },
+                    };
+                    arity.rest = true;
+                    (param, None)
+                }
+                pat => (self.visit_param_pat(pat, index, &mut prologue)?, None),
+            };
+
+            params.push(param);
+            arity.defaults.push(default);
+        }
+
+        for default in arity.defaults.iter().flatten() {
+            let vars = FreeVars::default().visit_expr(default);
+            if let Some(param) = params.iter().find(|param| vars.contains(&param.name.ident)) {
+                return Err(IRError::DefaultParamNotSupported(param.name.ident.clone()));
+            }
+        }
+
+        let mut used = names(&body.body);
+        used.extend(names(&prologue));
+        used.extend(params.iter().map(|param| param.name.ident.clone()));
+        for default in arity.defaults.iter().flatten() {
+            used.extend(FreeVars::default().visit_expr(default));
+        }
+
+        for (index, param) in params.iter_mut().enumerate() {
+            let placeholder = destructured_param(index);
+            if param.name.ident != placeholder {
+                continue;
+            }
+
+            let name = fresh_name(&format!("param_{}", index), &used);
+            used.insert(name.clone());
+            for ir in &mut prologue {
+                if let IR::PatDef(IRPatDef { value, .. }) = ir {
+                    if matches!(value.as_ref(), IRExpr::Identifier(v) if v.ident == placeholder) {
+                        let ident = name.clone();
+                        *value = Box::new(IRExpr::Identifier(IRIdent { ident }));
+                    }
+                }
+            }
+            param.name.ident = name;
+        }
+
+        Ok((params, arity, prologue))
+    }
+
+    /// A parameter, see [Self::visit_params]
+    fn visit_param_pat(
+        &self,
+        pat: &Pat,
+        index: usize,
+        prologue: &mut Vec<IR>,
+    ) -> IRResult<IRNameTy> {
+        match pat {
+            Pat::Object(_) | Pat::Array(_) => {
+                let name = Box::new(IRIdent {
+                    ident: destructured_param(index),
+                });
+                let (pattern, ty) = self.visit_destructuring_pat(pat)?;
+
+                prologue.push(IR::PatDef(IRPatDef {
+                    pattern,
+                    ty: ty.clone(),
+                    // Parameters can be reassigned in JS
+                    is_mutable: true,
+                    value: Box::new(IRExpr::Identifier(name.as_ref().clone())),
+                }));
+
+                Ok(IRNameTy { name, ty })
+            }
+            pat => self.visit_pat_as_binding_ident(pat),
+        }
+    }
+
+    /// An object or array pattern along with its type annotation, if any
+    fn visit_destructuring_pat(&self, pat: &Pat) -> IRResult<(IRPattern, IRTy)> {
+        let type_ann = match pat {
+            Pat::Object(ObjectPat { type_ann, .. }) | Pat::Array(ArrayPat { type_ann, .. }) => {
+                type_ann
+            }
+            _ => return Err(IRError::PatNotSupported),
+        };
+
+        let ty = match type_ann {
+            None => IRTy::UnknownTy,
+            Some(ts_type_ann) => self.visit_ts_type_ann(ts_type_ann)?,
+        };
+
+        Ok((self.visit_pattern(pat)?, ty))
+    }
+
+    /// `x`, `{ owner, deadline: d }` or `[head, ...tail]`, at any depth.
+    /// Type annotations are only looked at on the outermost pattern.
+    fn visit_pattern(&self, pat: &Pat) -> IRResult<IRPattern> {
+        match pat {
+            Pat::Ident(BindingIdent { id, .. }) => self.visit_ident(id).map(IRPattern::Var),
+            Pat::Object(ObjectPat { props, .. }) => {
+                let mut prop_patterns = Vec::with_capacity(props.len());
+                for prop in props {
+                    let prop_pattern = match prop {
+                        // `deadline: d`
+                        ObjectPatProp::KeyValue(KeyValuePatProp { key, value }) => IRPropPattern {
+                            name: Box::new(self.visit_prop_name(key)?),
+                            pattern: self.visit_pattern(value.as_ref())?,
+                        },
+                        // `owner`
+                        ObjectPatProp::Assign(AssignPatProp {
+                            key, value: None, ..
+                        }) => {
+                            let name = self.visit_ident(key)?;
+                            IRPropPattern {
+                                name: Box::new(name.clone()),
+                                pattern: IRPattern::Var(name),
+                            }
+                        }
+                        // `owner = "nobody"` or `...rest`
+                        _ => return Err(IRError::ObjectPatNotSupported),
+                    };
+                    prop_patterns.push(prop_pattern);
+                }
+
+                Ok(IRPattern::Object(prop_patterns))
+            }
+            Pat::Array(ArrayPat { elems, .. }) => {
+                let mut elements = Vec::with_capacity(elems.len());
+                let mut rest = None;
+                for (i, elem) in elems.iter().enumerate() {
+                    match elem {
+                        None => elements.push(IRPattern::Discard),
+                        Some(Pat::Rest(RestPat { arg, .. })) if i + 1 == elems.len() => {
+                            rest = Some(Box::new(self.visit_pattern(arg.as_ref())?));
+                        }
+                        Some(pat) => elements.push(self.visit_pattern(pat)?),
+                    }
+                }
+
+                Ok(IRPattern::Array { elements, rest })
+            }
+            _ => Err(IRError::PatNotSupported),
+        }
     }
 
     /// Visit a [Pat] trying to match a [BindingIdent] and nothing else.
@@ -244,44 +1489,256 @@ impl JsToIR {
         }
     }
 
-    fn visit_vardecl(&self, vardecl: &VarDecl) -> IRResult<IRVarDef> {
-        if vardecl.decls.len() != 1 {
-            Err(IRError::VarDeclLenNotSupported)
-        } else {
-            match vardecl.kind {
-                VarDeclKind::Var => Err(IRError::VarNotSupported),
+    /// One [IRVarDef] per declared variable, or an [IRPatDef] when destructuring
+    fn visit_vardecl(&self, vardecl: &VarDecl) -> IRResult<Vec<IR>> {
+        let is_mutable = match vardecl.kind {
+            VarDeclKind::Var => return Err(IRError::VarNotSupported),
+            VarDeclKind::Let => true,
+            VarDeclKind::Const => false,
+        };
+
+        let mut vardefs = Vec::with_capacity(vardecl.decls.len());
+        for decl in &vardecl.decls {
+            // get the assignment expression
+            // Note that we require an expression to exist, there is no `undefined`
+            let value = match &decl.init {
+                None => return Err(IRError::VarDeclNoInitializerNotSupported),
+                Some(expr) => Box::new(self.visit_expr(expr.as_ref())?),
+            };
 
-                VarDeclKind::Let | VarDeclKind::Const => {
-                    let is_mutable = vardecl.kind == VarDeclKind::Let;
-                    let decl = &vardecl.decls[0];
+            let vardef = match &decl.name {
+                // `const { owner, deadline } = datum`
+                pat @ (Pat::Object(_) | Pat::Array(_)) => {
+                    let (pattern, ty) = self.visit_destructuring_pat(pat)?;
 
+                    IR::PatDef(IRPatDef {
+                        pattern,
+                        ty,
+                        is_mutable,
+                        value,
+                    })
+                }
+                pat => {
                     // get name and type
-                    // Note that there should always be an identifier here, hence the unwrap()
-                    let namety = self.visit_pat_as_binding_ident(&decl.name)?;
-
-                    // get the assignment expression
-                    // Note that we require an expression to exist, otherwise it's a syntax error
-                    match &decl.init {
-                        None => Err(IRError::VarDeclNoInitializerNotSupported),
-
-                        Some(expr_box) => {
-                            let expr = expr_box.as_ref();
-                            let value = self.visit_expr(expr)?;
-
-                            let name = namety.name;
-                            let ty = namety.ty;
-
-                            Ok(IRVarDef {
-                                name,
-                                ty,
-                                is_mutable,
-                                value: Box::new(value),
-                            })
-                        }
+                    let namety = self.visit_pat_as_binding_ident(pat)?;
+
+                    IR::VarDef(IRVarDef {
+                        name: namety.name,
+                        ty: namety.ty,
+                        is_mutable,
+                        value,
+                    })
+                }
+            };
+            vardefs.push(vardef);
+        }
+
+        Ok(vardefs)
+    }
+
+    /// `interface Datum { owner: string; deadline: bigint }` becomes a record type,
+    /// and `interface Box<T> { value: T }` a generic one.
+    fn visit_interface(&self, interface: &TsInterfaceDecl) -> IRResult<IRTypeDef> {
+        if !interface.extends.is_empty() {
+            return Err(IRError::DeclarationNotSupported);
+        }
+
+        let name = self.visit_ident(&interface.id)?;
+        self.with_type_params(interface.type_params.as_deref(), |type_params| {
+            let fields = self.visit_ts_type_elements(&interface.body.body)?;
+
+            Ok(IRTypeDef {
+                type_params,
+                ..IRTypeDef::record(name, fields)
+            })
+        })
+    }
+
+    /// `type Datum = { owner: string; deadline: bigint }` becomes a record type.
+    /// `type Action = { kind: "Claim" } | { kind: "Cancel"; by: string }` becomes a type with
+    /// one constructor per member of the union.
+    /// Both may be generic, e.g. `type Result<T> = { kind: "Ok"; value: T } | { kind: "Err" }`.
+    fn visit_type_alias(&self, alias: &TsTypeAliasDecl) -> IRResult<IRTypeDef> {
+        let name = self.visit_ident(&alias.id)?;
+        self.with_type_params(alias.type_params.as_deref(), |type_params| {
+            let typedef = match alias.type_ann.as_ref() {
+                TsType::TsTypeLit(type_lit) => {
+                    let fields = self.visit_ts_type_elements(&type_lit.members)?;
+                    IRTypeDef::record(name, fields)
+                }
+                TsType::TsUnionOrIntersectionType(TsUnionOrIntersectionType::TsUnionType(
+                    union,
+                )) => self.visit_tagged_union(name, union)?,
+                _ => return Err(IRError::DeclarationNotSupported),
+            };
+
+            Ok(IRTypeDef {
+                type_params,
+                ..typedef
+            })
+        })
+    }
+
+    /// A union of object types that share a property with a string literal type,
+    /// the discriminant, whose value tells the members apart.
+    fn visit_tagged_union(&self, name: IRIdent, union: &TsUnionType) -> IRResult<IRTypeDef> {
+        let mut members = Vec::with_capacity(union.types.len());
+        for ty in &union.types {
+            match ty.as_ref() {
+                TsType::TsTypeLit(type_lit) => {
+                    let mut properties = Vec::with_capacity(type_lit.members.len());
+                    for element in &type_lit.members {
+                        properties.push(self.visit_ts_property(element)?);
                     }
+                    members.push(properties);
                 }
+                _ => return Err(IRError::UnionNotSupported(name.ident)),
             }
         }
+
+        // The discriminant is the first property that has a string literal type in all members
+        let discriminant = members
+            .first()
+            .into_iter()
+            .flatten()
+            .map(|(name, _, _)| name.ident.clone())
+            .find(|discriminant| {
+                members
+                    .iter()
+                    .all(|properties| tag_of(properties, discriminant).is_some())
+            })
+            .ok_or_else(|| IRError::UnionWithoutDiscriminant(name.ident.clone()))?;
+
+        let mut constructors = Vec::with_capacity(members.len());
+        for properties in &members {
+            // Safe, since this is how we found the discriminant
+            let tag = tag_of(properties, &discriminant).unwrap();
+
+            let mut fields = Vec::with_capacity(properties.len());
+            for (field, ty, optional) in properties {
+                if field.ident != discriminant {
+                    fields.push(IRNameTy {
+                        name: Box::new(field.clone()),
+                        ty: self.visit_ts_property_type(ty, *optional)?,
+                    });
+                }
+            }
+
+            constructors.push(IRConstructor {
+                name: Box::new(IRIdent {
+                    ident: constructor_name_of_tag(&name.ident, &tag),
+                }),
+                tag: Some(tag),
+                fields,
+            });
+        }
+
+        Ok(IRTypeDef {
+            name: Box::new(name),
+            type_params: vec![],
+            discriminant: Some(discriminant),
+            constructors,
+        })
+    }
+
+    /// The members of an interface or an object type, which must all be properties.
+    fn visit_ts_type_elements(&self, elements: &[TsTypeElement]) -> IRResult<Vec<IRNameTy>> {
+        let mut fields = Vec::with_capacity(elements.len());
+        for element in elements {
+            let (name, ty, optional) = self.visit_ts_property(element)?;
+            let ty = self.visit_ts_property_type(ty, optional)?;
+
+            fields.push(IRNameTy {
+                name: Box::new(name),
+                ty,
+            });
+        }
+
+        Ok(fields)
+    }
+
+    /// A property of an interface or an object type, `name: type`, and whether it is
+    /// optional, as in `name?: type`
+    fn visit_ts_property<'a>(
+        &self,
+        element: &'a TsTypeElement,
+    ) -> IRResult<(IRIdent, &'a TsType, bool)> {
+        match element {
+            TsTypeElement::TsPropertySignature(property) => {
+                let name = match property.key.as_ref() {
+                    Expr::Ident(ident) if !property.computed => self.visit_ident(ident)?,
+                    _ => return Err(IRError::TsTypeMemberNotSupported),
+                };
+                let ty = match &property.type_ann {
+                    None => return Err(IRError::TsTypeMemberNotSupported),
+                    Some(ts_type_ann) => ts_type_ann.type_ann.as_ref(),
+                };
+
+                Ok((name, ty, property.optional))
+            }
+            _ => Err(IRError::TsTypeMemberNotSupported),
+        }
+    }
+
+    /// The type of a property, which is an option if the property is optional
+    fn visit_ts_property_type(&self, ty: &TsType, optional: bool) -> IRResult<IRTy> {
+        let ty = self.visit_ts_type(ty)?;
+
+        Ok(if optional { ty.optional() } else { ty })
+    }
+
+    /// `switch (a.kind) { case "Claim": ...; default: ... }`
+    ///
+    /// Each case must end with `break`, `return` or `throw`, since there is no fallthrough,
+    /// unless it is empty, in which case it shares the body of the next one.
+    fn visit_switchstmt(&self, switchstmt: &SwitchStmt) -> IRResult<IRSwitchStmt> {
+        let discriminant = self.visit_expr(switchstmt.discriminant.as_ref())?;
+
+        let mut cases = Vec::with_capacity(switchstmt.cases.len());
+        let mut tests = Vec::new();
+        let mut seen_default = false;
+        for case in &switchstmt.cases {
+            // `default` must come last, so that we do not have to reorder the cases
+            if seen_default {
+                return Err(IRError::SwitchCaseNotSupported);
+            }
+
+            match &case.test {
+                Some(test) => tests.push(self.visit_expr(test.as_ref())?),
+                None => {
+                    // Cases that fall through to `default` are subsumed by it
+                    tests.clear();
+                    seen_default = true;
+                }
+            }
+
+            let mut stmts = case.cons.as_slice();
+            if stmts.is_empty() && case.test.is_some() {
+                continue;
+            }
+
+            match stmts.last() {
+                Some(Stmt::Break(BreakStmt { label: None, .. })) => {
+                    stmts = &stmts[..stmts.len() - 1];
+                }
+                Some(Stmt::Return(_)) | Some(Stmt::Throw(_)) => {}
+                _ if case.test.is_none() => {}
+                _ => return Err(IRError::SwitchFallthroughNotSupported),
+            }
+
+            let body = self.visit_stmts(stmts)?;
+
+            cases.push(IRSwitchCase {
+                tests: std::mem::take(&mut tests),
+                body: IRBlockStmt { body },
+            });
+        }
+
+        Ok(IRSwitchStmt {
+            discriminant: Box::new(discriminant),
+            cases,
+            span: switchstmt.span,
+        })
     }
 
     fn visit_ifstmt(&self, ifstmt: &IfStmt) -> IRResult<IRIfStmt> {
@@ -304,17 +1761,9 @@ impl JsToIR {
     }
 
     fn visit_blockstmt(&self, blockstmt: &BlockStmt) -> IRResult<IRBlockStmt> {
-        let stmts = &blockstmt.stmts;
+        let body = self.visit_stmts(&blockstmt.stmts)?;
 
-        let blen = stmts.len();
-        let mut nodes = Vec::with_capacity(blen);
-
-        for stmt in stmts {
-            let ir = self.visit_stmt(stmt)?;
-            nodes.push(ir);
-        }
-
-        Ok(IRBlockStmt { body: nodes })
+        Ok(IRBlockStmt { body })
     }
 
     fn visit_ts_type_ann(&self, ts_type_ann: &TsTypeAnn) -> IRResult<IRTy> {
@@ -324,14 +1773,130 @@ impl JsToIR {
     fn visit_ts_type(&self, ts_type: &TsType) -> IRResult<IRTy> {
         match ts_type {
             TsType::TsKeywordType(kwd_type) => self.visit_ts_keyword_type(kwd_type),
+            TsType::TsFnOrConstructorType(TsFnOrConstructorType::TsFnType(fn_type)) => {
+                self.visit_ts_fn_type(fn_type)
+            }
+            TsType::TsTypeRef(type_ref) => self.visit_ts_type_ref(type_ref),
+            TsType::TsArrayType(array_type) => {
+                let elem_ty = self.visit_ts_type(&array_type.elem_type)?;
+                Ok(IRTy::ListTy(Box::new(elem_ty)))
+            }
+            TsType::TsTupleType(tuple_type) => {
+                let mut elem_tys = Vec::with_capacity(tuple_type.elem_types.len());
+                for element in &tuple_type.elem_types {
+                    elem_tys.push(self.visit_ts_type(&element.ty)?);
+                }
+                Ok(IRTy::TupleTy(elem_tys))
+            }
+            TsType::TsUnionOrIntersectionType(TsUnionOrIntersectionType::TsUnionType(union)) => {
+                self.visit_ts_optional_type(union)
+            }
+            _ => Err(IRError::TsTypeNotSupported),
+        }
+    }
+
+    /// `T | undefined`, the only union that is not a declared type, see [Self::visit_type_alias]
+    fn visit_ts_optional_type(&self, union: &TsUnionType) -> IRResult<IRTy> {
+        let is_undefined = |ty: &TsType| {
+            matches!(
+                ty,
+                TsType::TsKeywordType(TsKeywordType {
+                    kind: TsKeywordTypeKind::TsUndefinedKeyword,
+                    ..
+                })
+            )
+        };
+        let defined: Vec<&TsType> = union
+            .types
+            .iter()
+            .map(|ty| ty.as_ref())
+            .filter(|ty| !is_undefined(ty))
+            .collect();
+
+        match defined.as_slice() {
+            [ty] if defined.len() < union.types.len() => Ok(self.visit_ts_type(ty)?.optional()),
+            _ => Err(IRError::TsTypeNotSupported),
+        }
+    }
+
+    /// References to user-defined types, e.g. `Datum` or `Box<bigint>`, to type parameters,
+    /// and to `Array<T>`
+    fn visit_ts_type_ref(&self, type_ref: &TsTypeRef) -> IRResult<IRTy> {
+        let type_var = |ident: &Ident| {
+            let type_params = self.type_params.borrow();
+            type_params
+                .iter()
+                .rev()
+                .find(|(name, _)| *name == *ident.sym)
+                .map(|(_, var)| var.clone())
+        };
+
+        match (&type_ref.type_name, &type_ref.type_params) {
+            (TsEntityName::Ident(ident), None) if type_var(ident).is_some() => {
+                Ok(IRTy::VarTy(type_var(ident).unwrap()))
+            }
+            (TsEntityName::Ident(ident), None)
+                if &*ident.sym == "Uint8Array" || &*ident.sym == "ByteArray" =>
+            {
+                Ok(IRTy::ByteArrayTy)
+            }
+            (TsEntityName::Ident(ident), None) => Ok(IRTy::NamedTy(ident.sym.to_string(), vec![])),
+            (TsEntityName::Ident(ident), Some(type_params))
+                if &*ident.sym == "Array" && type_params.params.len() == 1 =>
+            {
+                let elem_ty = self.visit_ts_type(&type_params.params[0])?;
+                Ok(IRTy::ListTy(Box::new(elem_ty)))
+            }
+            (TsEntityName::Ident(ident), Some(type_params)) => {
+                let mut type_args = Vec::with_capacity(type_params.params.len());
+                for param in &type_params.params {
+                    type_args.push(self.visit_ts_type(param)?);
+                }
+
+                Ok(IRTy::NamedTy(ident.sym.to_string(), type_args))
+            }
             _ => Err(IRError::TsTypeNotSupported),
         }
     }
 
+    /// Function types, e.g. `(x: number) => number`
+    fn visit_ts_fn_type(&self, fn_type: &TsFnType) -> IRResult<IRTy> {
+        // A generic function cannot be passed around, aiken instantiates it where it is named
+        if fn_type.type_params.is_some() {
+            return Err(IRError::TsTypeNotSupported);
+        }
+
+        let mut param_tys = Vec::with_capacity(fn_type.params.len());
+        for param in &fn_type.params {
+            match param {
+                TsFnParam::Ident(BindingIdent { type_ann, .. }) => {
+                    let ty = match type_ann {
+                        None => IRTy::UnknownTy,
+                        Some(ts_type_ann) => self.visit_ts_type_ann(ts_type_ann)?,
+                    };
+                    param_tys.push(ty);
+                }
+                _ => return Err(IRError::PatNotSupported),
+            }
+        }
+
+        let ret_ty = Box::new(self.visit_ts_type_ann(&fn_type.type_ann)?);
+        let fun_ty = IRFunTy {
+            type_params: vec![],
+            param_tys,
+            ret_ty,
+        };
+
+        Ok(IRTy::FunTy(Box::new(fun_ty)))
+    }
+
     fn visit_ts_keyword_type(&self, kwd_type: &TsKeywordType) -> IRResult<IRTy> {
         let kind = kwd_type.kind;
         match kind {
-            TsKeywordTypeKind::TsNumberKeyword => Ok(IRTy::Float64Ty),
+            TsKeywordTypeKind::TsNumberKeyword => match self.options.numbers {
+                NumericSemantics::BigIntOnly => Err(IRError::NumberNotAllowed("number".into())),
+                _ => Ok(IRTy::Float64Ty),
+            },
             TsKeywordTypeKind::TsBooleanKeyword => Ok(IRTy::BooleanTy),
             TsKeywordTypeKind::TsBigIntKeyword => Ok(IRTy::BigIntTy),
             TsKeywordTypeKind::TsStringKeyword => Ok(IRTy::StringTy),
@@ -344,3 +1909,86 @@ impl JsToIR {
         self.visit_expr(expr.expr.as_ref())
     }
 }
+
+/// `code`, a Typescript script, parsed by `swc`
+#[cfg(test)]
+fn parse_swc_script(code: &str) -> Script {
+    use swc_core::common::BytePos;
+    use swc_core::ecma::parser::{Parser, StringInput, Syntax, TsConfig};
+
+    let input = StringInput::new(code, BytePos(0), BytePos(code.len() as u32));
+    let mut parser = Parser::new(Syntax::Typescript(TsConfig::default()), input, None);
+    parser.parse_script().expect("a valid script")
+}
+
+/// `code`, a Typescript script, lowered to our IR with `options`
+#[cfg(test)]
+pub(crate) fn parse_script(code: &str, options: CompilerOptions) -> IRResult<IR> {
+    JsToIR::new(options).visit_script(&parse_swc_script(code))
+}
+
+/// The statements of `code`, the body of a Typescript function, lowered to our IR
+/// but neither normalised nor in SSA form, for the tests of these passes.
+#[cfg(test)]
+pub(crate) fn parse_stmts(code: &str) -> Vec<IR> {
+    let script = parse_swc_script(&format!("function f() {{\n{}\n}}", code));
+    let body = match script.body.as_slice() {
+        [Stmt::Decl(Decl::Fn(FnDecl { function, .. }))] => function.body.as_ref().unwrap(),
+        _ => unreachable!(),
+    };
+
+    let js = JsToIR::new(CompilerOptions::default());
+    js.visit_blockstmt(body).expect("a supported function").body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_traces(traces: Traces) -> CompilerOptions {
+        CompilerOptions {
+            traces,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn invalid_logs_fail_whether_traces_are_kept_or_stripped() {
+        for traces in [Traces::Keep, Traces::Strip] {
+            let spread = "function f(xs: bigint[]) { console.log(...xs); }";
+            assert!(matches!(
+                parse_script(spread, with_traces(traces)),
+                Err(IRError::SpreadNotSupported)
+            ));
+
+            let typeof_ = "function f(x: bigint) { console.log(typeof x); }";
+            assert!(matches!(
+                parse_script(typeof_, with_traces(traces)),
+                Err(IRError::UnaryOpNotSupported { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn stripped_logs_must_use_defined_variables() {
+        let undefined = "function f(x: bigint) { console.log(x, y); }";
+        assert!(matches!(
+            parse_script(undefined, with_traces(Traces::Strip)),
+            Err(IRError::UndefinedInTrace(y)) if y == "y"
+        ));
+
+        let defined = "
+            function limit(): bigint {
+                return 10n;
+            }
+
+            function f(x: bigint): bigint {
+                const g = (y: bigint): bigint => {
+                    console.log(x, y, limit());
+                    return y;
+                };
+                return g(x);
+            }";
+        assert!(parse_script(defined, with_traces(Traces::Strip)).is_ok());
+    }
+}
@@ -2,10 +2,14 @@
 #![allow(unreachable_code)]
 #![allow(unused_variables)]
 
+pub mod aiken_prelude;
 pub mod copy_aiken_project_lib;
 pub mod ir;
 pub mod ir2;
 pub mod ir2_visitor;
+pub mod ir_free_vars;
+pub mod ir_normalize;
+pub mod ir_ssa;
 pub mod ir_to_unode;
 pub mod ir_type;
 pub mod ir_visitor;
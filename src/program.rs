@@ -3,6 +3,7 @@ use std::fmt::Debug;
 use std::path::PathBuf;
 use std::rc::Rc;
 
+use num_bigint::BigInt;
 use thiserror::Error;
 
 use crate::ir::IR;
@@ -32,6 +33,95 @@ pub struct Source<X> {
     pub code: String,
 }
 
+/// `Number.MAX_SAFE_INTEGER`, the largest integer a `number` represents exactly
+pub const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+/// What a JS `number` means on-chain, where there are only (arbitrary precision) integers.
+/// A `bigint` is always an integer, whose `/` truncates towards zero.
+#[derive(PartialEq, Eq, Debug, Default, Serialize, Deserialize, Copy, Clone)]
+pub enum NumericSemantics {
+    /// `number` is rejected, programs must use `bigint`
+    BigIntOnly,
+    /// `number`s are safe integers and `/` truncates towards zero, like `Math.trunc(a / b)`
+    #[default]
+    SafeInteger,
+    /// `number`s are decimals with `scale` fractional digits, represented by integers
+    /// multiplied by `10^scale`, e.g. `1.5` is `150` with a scale of 2
+    FixedPoint { scale: u32 },
+}
+
+impl NumericSemantics {
+    /// The on-chain integer for the `number` literal `value`, if it can be represented.
+    pub fn number_literal(&self, value: f64) -> Option<BigInt> {
+        if !value.is_finite() || value.abs() > MAX_SAFE_INTEGER {
+            return None;
+        }
+
+        match self {
+            NumericSemantics::BigIntOnly => None,
+            NumericSemantics::SafeInteger if value.fract() == 0.0 => {
+                Some(BigInt::from(value as i64))
+            }
+            NumericSemantics::SafeInteger => None,
+            NumericSemantics::FixedPoint { scale } => {
+                // The shortest representation of `value`, which never has an exponent,
+                // so that `0.1` is exactly `1 / 10` rather than its binary approximation
+                let repr = value.abs().to_string();
+                let (int, frac) = repr.split_once('.').unwrap_or((&repr, ""));
+                let padding = (*scale as usize).checked_sub(frac.len())?;
+
+                let digits: BigInt = format!("{}{}{}", int, frac, "0".repeat(padding))
+                    .parse()
+                    .ok()?;
+
+                Some(if value < 0.0 { -digits } else { digits })
+            }
+        }
+    }
+
+    /// The on-chain integer for `1`, which is `10^scale` for fixed-point numbers.
+    pub fn one(&self) -> BigInt {
+        match self {
+            NumericSemantics::FixedPoint { scale } => BigInt::from(10).pow(*scale),
+            _ => BigInt::from(1),
+        }
+    }
+}
+
+/// Which values can be used as conditions, in `if`, `while`, `? :`, `!`, and in `&&` and `||`
+/// when these are themselves conditions.
+#[derive(PartialEq, Eq, Debug, Default, Serialize, Deserialize, Copy, Clone)]
+pub enum Truthiness {
+    /// Only `boolean`s, anything else is rejected
+    #[default]
+    BooleanOnly,
+    /// Also numbers, which are truthy unless `0`, and strings and lists, which are truthy
+    /// unless empty. Objects are rejected, since they would always be truthy.
+    /// Optional values are truthy when they are defined and their value is truthy, which
+    /// for objects tells whether they are defined.
+    Coerce,
+}
+
+/// Whether the compiled scripts trace messages, for `console.log` and in the labels of
+/// the `error`s of `throw` and `assert`
+#[derive(PartialEq, Eq, Debug, Default, Serialize, Deserialize, Copy, Clone)]
+pub enum Traces {
+    /// Messages show up in the logs of the evaluation of the scripts
+    #[default]
+    Keep,
+    /// No traces at all, for production builds, so that the scripts and their hashes are
+    /// the same as without the debug logging
+    Strip,
+}
+
+/// Options that change the meaning of the compiled programs
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone)]
+pub struct CompilerOptions {
+    pub numbers: NumericSemantics,
+    pub truthiness: Truthiness,
+    pub traces: Traces,
+}
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Error while parsing")]
@@ -44,6 +134,10 @@ pub enum Error {
     Aiken(aiken_project::error::Error),
     #[error("Bad file name")]
     BadFilename(PathBuf),
+    #[error("Cannot resolve imported module")]
+    ModuleNotFound { importer: PathBuf, src: String },
+    #[error("Imported module is outside of the entry point's directory")]
+    ImportOutsideRoot { importer: PathBuf, src: String },
 }
 
 #[derive(Debug)]
@@ -51,3 +145,59 @@ pub struct Program<X> {
     pub source: Rc<Source<X>>,
     pub ir: IR,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed(scale: u32) -> NumericSemantics {
+        NumericSemantics::FixedPoint { scale }
+    }
+
+    #[test]
+    fn fixed_point_literals_are_scaled_exactly() {
+        assert_eq!(fixed(2).number_literal(0.1), Some(BigInt::from(10)));
+        assert_eq!(fixed(2).number_literal(1.5), Some(BigInt::from(150)));
+        assert_eq!(fixed(2).number_literal(3.0), Some(BigInt::from(300)));
+        assert_eq!(fixed(0).number_literal(42.0), Some(BigInt::from(42)));
+    }
+
+    #[test]
+    fn fixed_point_negative_literals() {
+        assert_eq!(fixed(2).number_literal(-0.1), Some(BigInt::from(-10)));
+        assert_eq!(fixed(2).number_literal(-2.25), Some(BigInt::from(-225)));
+    }
+
+    #[test]
+    fn fixed_point_literals_with_too_many_digits() {
+        assert_eq!(fixed(2).number_literal(0.125), None);
+        assert_eq!(fixed(0).number_literal(0.5), None);
+        assert_eq!(fixed(6).number_literal(1e-7), None);
+    }
+
+    #[test]
+    fn fixed_point_scale_does_not_overflow() {
+        // `MAX_SAFE_INTEGER * 10^30` is well beyond 64 bits
+        let expected = BigInt::from(MAX_SAFE_INTEGER as i64) * BigInt::from(10).pow(30);
+        assert_eq!(fixed(30).number_literal(MAX_SAFE_INTEGER), Some(expected));
+        assert_eq!(fixed(30).one(), BigInt::from(10).pow(30));
+    }
+
+    #[test]
+    fn unsafe_numbers_are_rejected() {
+        for numbers in [NumericSemantics::SafeInteger, fixed(2)] {
+            assert_eq!(numbers.number_literal(1e21), None);
+            assert_eq!(numbers.number_literal(f64::NAN), None);
+            assert_eq!(numbers.number_literal(f64::INFINITY), None);
+        }
+    }
+
+    #[test]
+    fn safe_integer_literals() {
+        let numbers = NumericSemantics::SafeInteger;
+        assert_eq!(numbers.number_literal(7.0), Some(BigInt::from(7)));
+        assert_eq!(numbers.number_literal(-7.0), Some(BigInt::from(-7)));
+        assert_eq!(numbers.number_literal(0.1), None);
+        assert_eq!(NumericSemantics::BigIntOnly.number_literal(1.0), None);
+    }
+}